@@ -1,10 +1,16 @@
+use std::collections::BTreeMap;
 use std::time::Duration;
 
 use crate::event::Event;
 use crate::util;
+use crate::error::ConfigError;
 use crate::Error;
 use super::{RunConfig,EventConfig};
-use super::serializer::DeviceConfigSerializer;
+use super::event::expand_events;
+use super::pipe::{PipeConfig,map_pipes};
+use super::serializer::{DeviceConfigSerializer,PageConfigSerializer,TimerConfigSerializer,IdleConfigSerializer};
+
+use serde::Deserialize;
 
 #[derive(Debug,Clone)]
 pub enum Identifier {
@@ -14,16 +20,78 @@ pub enum Identifier {
     Addr(String),
 }
 
+#[derive(Deserialize,Debug,Clone,Copy,PartialEq,Eq,Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Overflow {
+    #[default]
+    DropOldest,
+    DropNewest,
+    Coalesce,
+}
+
+#[derive(Deserialize,Debug,Clone,Copy,PartialEq,Eq,Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+    First,
+    #[default]
+    All,
+}
+
+#[derive(Debug,Clone)]
+pub struct PageConfig {
+    pub name: String,
+    pub events: Vec<EventConfig>,
+}
+
+#[derive(Debug,Clone)]
+pub struct TimerConfig {
+    pub interval: Duration,
+    pub run: Vec<RunConfig>,
+}
+
+#[derive(Debug,Clone)]
+pub struct IdleConfig {
+    // time without input before the device is idle
+    pub after: Duration,
+    pub run: Vec<RunConfig>,
+    // run on the first event once idle
+    pub resume: Vec<RunConfig>,
+}
+
+// Watchdog on Active Sensing, armed by the first one received
+#[derive(Debug,Clone)]
+pub struct SensingConfig {
+    // time without Active Sensing before the link is lost
+    pub timeout: Duration,
+    pub lost: Vec<RunConfig>,
+    pub resumed: Vec<RunConfig>,
+    // run disconnect commands and stop timers on loss, connect commands on recovery
+    pub disconnect: bool,
+}
+
 #[derive(Debug,Clone)]
 pub struct DeviceConfig {
     pub identifier: Identifier,
     pub max_connections: Option<u32>,
     pub connect: Option<Vec<RunConfig>>,
     pub disconnect: Option<Vec<RunConfig>>,
+    pub unmatched: Vec<RunConfig>,
     pub events: Option<Vec<EventConfig>>,
+    pub pages: Vec<PageConfig>,
+    pub default_page: Option<String>,
     pub queue_length: usize,
+    pub overflow: Overflow,
     pub interval: Duration,
     pub log: bool,
+    pub r#match: MatchMode,
+    // variables of each connected device and their initial values
+    pub vars: BTreeMap<String, i64>,
+    // run lists repeated while the device is connected
+    pub timers: Vec<TimerConfig>,
+    pub idle: Option<IdleConfig>,
+    pub sensing: SensingConfig,
+    // pipes of each connected device
+    pub pipes: Vec<PipeConfig>,
 }
 
 impl DeviceConfig {
@@ -42,6 +110,17 @@ impl DeviceConfig {
         Ok(r)
     }
 
+    // position of a page in pages, None for the base layer
+    pub fn page_index(&self, name: &str) -> Result<Option<usize>, ConfigError> {
+        if name == "base" {
+            return Ok(None);
+        }
+        match self.pages.iter().position(|x| x.name == name) {
+            Some(i) => Ok(Some(i)),
+            None => Err(ConfigError::UnknownPage(name.to_string())),
+        }
+    }
+
     pub fn run_connect(&self) -> Result<Vec<std::process::ExitStatus>, Error> {
         self.run_internal(self.connect.as_ref())
     }
@@ -54,7 +133,14 @@ impl DeviceConfig {
 impl TryFrom<DeviceConfigSerializer> for DeviceConfig {
     type Error = crate::Error;
     fn try_from(v: DeviceConfigSerializer) -> Result<Self, Self::Error> {
-        Ok(DeviceConfig {
+        let sensing_timeout = v.sensing_timeout.map(|x| x.unwrap()).unwrap_or(Duration::from_millis(300));
+        if sensing_timeout.is_zero() {
+            return Err(Error::from(ConfigError::SensingTimeout));
+        }
+        if v.queue_length == Some(0) {
+            return Err(Error::from(ConfigError::QueueLength));
+        }
+        let r = DeviceConfig {
             identifier: {
                 match (v.name, v.regex, v.addr) {
                     (Some(_), Some(_), _      ) => return Err(Error::IncompatibleArgs("name","regex")),
@@ -69,10 +155,81 @@ impl TryFrom<DeviceConfigSerializer> for DeviceConfig {
             max_connections: v.max_connections,
             connect:    util::map_opt_tryfrom(v.connect)?,
             disconnect: util::map_opt_tryfrom(v.disconnect)?,
-            events:     util::map_opt_tryfrom(v.events)?,
+            unmatched:  util::map_opt_tryfrom(v.unmatched)?.unwrap_or_default(),
+            events:     v.events.map(expand_events).transpose()?,
+            pages: match v.pages {
+                Some(p) => p.into_iter().map(PageConfig::try_from).collect::<Result<Vec<PageConfig>, Error>>()?,
+                None => Vec::new(),
+            },
+            default_page: v.default_page,
             queue_length: v.queue_length.unwrap_or(256),
+            overflow: v.overflow.unwrap_or_default(),
             interval: v.interval.map(|x| x.unwrap()).unwrap_or_else(|| Duration::new(0, 0)),
             log: v.log_events.unwrap_or(false),
+            r#match: v.r#match.unwrap_or_default(),
+            vars: v.vars.unwrap_or_default(),
+            timers: util::map_opt_tryfrom(v.timers)?.unwrap_or_default(),
+            idle: v.idle.map(IdleConfig::try_from).transpose()?,
+            sensing: SensingConfig {
+                timeout: sensing_timeout,
+                lost: util::map_opt_tryfrom(v.sensing_lost)?.unwrap_or_default(),
+                resumed: util::map_opt_tryfrom(v.sensing_resumed)?.unwrap_or_default(),
+                disconnect: v.sensing_disconnect.unwrap_or(false),
+            },
+            pipes: map_pipes(v.pipes)?,
+        };
+        // check page references
+        if let Some(p) = &r.default_page {
+            r.page_index(p)?;
+        }
+        let pages = r.pages.iter().flat_map(|x| x.events.iter());
+        for e in r.events.iter().flatten().chain(pages) {
+            if let Some(p) = &e.page {
+                r.page_index(p)?;
+            }
+        }
+        Ok(r)
+    }
+}
+
+impl TryFrom<TimerConfigSerializer> for TimerConfig {
+    type Error = crate::Error;
+    fn try_from(v: TimerConfigSerializer) -> Result<Self, Self::Error> {
+        let interval = v.interval.unwrap();
+        if interval.is_zero() {
+            return Err(Error::from(ConfigError::TimerInterval));
+        }
+        Ok(TimerConfig {
+            interval,
+            run: util::map_tryfrom(v.run)?,
+        })
+    }
+}
+
+impl TryFrom<IdleConfigSerializer> for IdleConfig {
+    type Error = crate::Error;
+    fn try_from(v: IdleConfigSerializer) -> Result<Self, Self::Error> {
+        let after = v.after.unwrap();
+        if after.is_zero() {
+            return Err(Error::from(ConfigError::IdleAfter));
+        }
+        Ok(IdleConfig {
+            after,
+            run: util::map_opt_tryfrom(v.run)?.unwrap_or_default(),
+            resume: util::map_opt_tryfrom(v.resume)?.unwrap_or_default(),
+        })
+    }
+}
+
+impl TryFrom<(String, PageConfigSerializer)> for PageConfig {
+    type Error = crate::Error;
+    fn try_from((name, v): (String, PageConfigSerializer)) -> Result<Self, Self::Error> {
+        if name == "base" {
+            return Err(Error::from(ConfigError::ReservedPageName));
+        }
+        Ok(PageConfig {
+            name,
+            events: expand_events(v.events)?,
         })
     }
 }
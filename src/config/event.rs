@@ -1,35 +1,128 @@
 use super::RunConfig;
+use super::device::Overflow;
+use super::var::VarAction;
+use super::when::When;
 use crate::event::{Event,EventType};
+use crate::gesture::Gesture;
+use crate::state::DeviceState;
 use crate::util::{self, SmartSet, Range, Remapper};
+use crate::error::ConfigError;
+use crate::Error;
 
-use super::serializer::EventConfigSerializer;
+use super::serializer::{EventConfigSerializer,SequenceStepSerializer,RemapSerializer};
 
-use std::collections::BTreeSet;
+use std::time::{Duration, SystemTime};
+
+use serde::Deserialize;
 
 use lazy_static::lazy_static;
 
 lazy_static! {
-    static ref ID_DEFAULT_MAP: SmartSet<u8> = SmartSet {
-        set: BTreeSet::from((0..=127).collect::<BTreeSet<u8>>()),
-    };
-    static ref NULL_DEFAULT_MAP: SmartSet<u8> = SmartSet {
-        set: BTreeSet::from([0]),
-    };
-    static ref CHANNEL_DEFAULT_MAP: SmartSet<u8> = SmartSet {
-        set: BTreeSet::from((0..=15).collect::<BTreeSet<u8>>()),
-    };
-    static ref TRIGGER_NOTE_DEFAULT_MAP: SmartSet<u16> = SmartSet {
-        set: BTreeSet::from((1..=127).collect::<BTreeSet<u16>>()),
-    };
-    static ref TRIGGER_U8_DEFAULT_MAP: SmartSet<u16> = SmartSet {
-        set: BTreeSet::from((0..=127).collect::<BTreeSet<u16>>()),
-    };
-    static ref TRIGGER_U16_DEFAULT_MAP: SmartSet<u16> = SmartSet {
-        set: BTreeSet::from((0..=65535).collect::<BTreeSet<u16>>()),
-    };
-    static ref TRIGGER_NULL_DEFAULT_MAP: SmartSet<u16> = SmartSet {
-        set: BTreeSet::from([0]),
-    };
+    static ref ID_DEFAULT_MAP: SmartSet<u8> = SmartSet::from_range(0, 127);
+    static ref NULL_DEFAULT_MAP: SmartSet<u8> = SmartSet::from(0);
+    static ref CHANNEL_DEFAULT_MAP: SmartSet<u8> = SmartSet::from_range(0, 15);
+    static ref TRIGGER_NOTE_DEFAULT_MAP: SmartSet<u16> = SmartSet::from_range(1, 127);
+    static ref TRIGGER_U8_DEFAULT_MAP: SmartSet<u16> = SmartSet::from_range(0, 127);
+    static ref TRIGGER_U16_DEFAULT_MAP: SmartSet<u16> = SmartSet::from_range(0, 65535);
+    static ref TRIGGER_NULL_DEFAULT_MAP: SmartSet<u16> = SmartSet::from(0);
+}
+
+#[derive(Deserialize,Debug,Clone,Copy,PartialEq,Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Trigger {
+    Rise,
+    Fall,
+    Change,
+}
+
+#[derive(Deserialize,Debug,Clone,Copy,PartialEq,Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChordMatch {
+    Exact,
+    Superset,
+}
+
+#[derive(Deserialize,Debug,Clone,Copy,PartialEq,Eq,Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PageMode {
+    #[default]
+    Switch,
+    Toggle,
+    Hold,
+}
+
+// Encoding of relative Controller values sent by endless encoders
+#[derive(Deserialize,Debug,Clone,Copy,PartialEq,Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Relative {
+    // 1 = +1, 127 = -1
+    TwosComplement,
+    // 1 = +1, 65 = -1
+    SignMagnitude,
+    // 65 = +1, 63 = -1
+    BinaryOffset,
+}
+
+impl Relative {
+    pub fn decode(&self, value: u16) -> i64 {
+        let v = (value & 0x7f) as i64;
+        match self {
+            Relative::TwosComplement if v >= 64 => v - 128,
+            Relative::TwosComplement => v,
+            Relative::SignMagnitude if v >= 64 => -(v - 64),
+            Relative::SignMagnitude => v,
+            Relative::BinaryOffset => v - 64,
+        }
+    }
+}
+
+#[derive(Debug,Clone)]
+pub struct Chord {
+    pub notes: SmartSet<u8>,
+    pub r#match: ChordMatch,
+    pub tolerance: Option<Duration>,
+    pub release: Vec<RunConfig>,
+}
+
+#[derive(Debug,Clone)]
+pub struct SequenceStep {
+    pub r#type: EventType,
+    pub channel: SmartSet<u8>,
+    pub id: SmartSet<u8>,
+    pub value: SmartSet<u16>,
+}
+
+#[derive(Debug,Clone)]
+pub struct Sequence {
+    pub steps: Vec<SequenceStep>,
+    pub timeout: Option<Duration>,
+}
+
+impl SequenceStep {
+    // event is of the kind of this step, regardless of channel and id
+    pub fn match_kind(&self, event: &Event) -> bool {
+        self.r#type == event.r#type && self.value.contains(&event.value)
+    }
+
+    pub fn matches(&self, event: &Event) -> bool {
+        self.match_kind(event) && self.channel.contains(&event.channel) && self.id.contains(&event.id)
+    }
+}
+
+#[derive(Debug,Clone)]
+pub struct Cycle {
+    // state name and run list of each step
+    pub steps: Vec<(String, Vec<RunConfig>)>,
+    // name of the state kept across reloads, shared by all devices
+    pub persist: Option<String>,
+}
+
+#[derive(Debug,Clone)]
+pub struct NamedRemap {
+    // env variable of the remapped value: value_<name>
+    pub key: String,
+    pub remap: Remapper<f64>,
+    pub float: bool,
 }
 
 #[derive(Debug,Clone)]
@@ -41,40 +134,449 @@ pub struct EventConfig {
     pub remap: Option<Remapper<f64>>,
     pub float: bool,
     pub value: Option<SmartSet<u16>>,
+    pub trigger: Option<Trigger>,
+    pub threshold: u16,
+    pub hysteresis: u16,
+    pub chord: Option<Chord>,
+    pub sequence: Option<Sequence>,
+    pub gesture: Option<Gesture>,
+    pub page: Option<String>,
+    pub page_mode: PageMode,
+    pub cycle: Option<Cycle>,
+    // executor settings, overriding the device ones
+    pub queue_length: Option<usize>,
+    pub overflow: Option<Overflow>,
+    pub interval: Option<Duration>,
+    // event configs of the same group share one executor
+    pub group: Option<String>,
+    pub relative: Option<Relative>,
+    // range of the accumulated value of relative controls
+    pub accumulate: Option<Range<i64>>,
+    pub remaps: Vec<NamedRemap>,
+    // higher priorities are matched first
+    pub priority: i32,
+    // no other event config runs after this one on the same event
+    pub r#final: bool,
+    // variable actions applied when triggered
+    pub vars: Vec<VarAction>,
+    // conditions on device state checked when triggered
+    pub when: Option<When>,
+    // pipes the event is written to when triggered
+    pub pipe: Vec<String>,
+    // Rhai script run when triggered, before commands
+    #[cfg(feature = "script")]
+    pub script: Option<crate::script::Script>,
 }
 
 impl EventConfig {
+    // conditions on device state, configs not meeting them are skipped like if they didn't match
+    pub fn match_when(&self, event: &Event, state: &DeviceState) -> bool {
+        self.when.as_ref().is_none_or(|w| w.matches(event, state))
+    }
+
     pub fn match_value(&self, event: &Event) -> bool {
         match &self.value {
-            Some(v) =>  v.set.contains(&event.value),
+            Some(v) =>  v.contains(&event.value),
             None => true,
         }
     }
+
+    // stateful transition check, to be called once per event
+    pub fn match_trigger(&self, event: &Event, state: &mut DeviceState) -> bool {
+        let (trigger, index) = match (self.trigger, event.index()) {
+            (Some(t), Some(i)) => (t, i),
+            _ => return true,
+        };
+        let key = (self as *const Self as usize, index);
+        let last = state.trigger(key);
+        match trigger {
+            Trigger::Change => {
+                if let Some(v) = last {
+                    if event.value.abs_diff(v) <= self.hysteresis {
+                        return false;
+                    }
+                }
+                state.set_trigger(key, event.value);
+                true
+            }
+            Trigger::Rise | Trigger::Fall => {
+                // unknown state is considered low
+                let high = last.unwrap_or(0) != 0;
+                let new_high = if event.value >= self.threshold.saturating_add(self.hysteresis) {
+                    true
+                } else if event.value < self.threshold.saturating_sub(self.hysteresis) {
+                    false
+                } else {
+                    high
+                };
+                state.set_trigger(key, new_high as u16);
+                match trigger {
+                    Trigger::Rise => !high && new_high,
+                    _ => high && !new_high,
+                }
+            }
+        }
+    }
+
+    // sequence completed by event. Events not of the kind of any step are ignored,
+    // other events that don't match the next step reset progress
+    pub fn match_sequence(&self, event: &Event, state: &mut DeviceState) -> bool {
+        let seq = match &self.sequence {
+            Some(s) => s,
+            None => return false,
+        };
+        if !seq.steps.iter().any(|x| x.match_kind(event)) {
+            return false;
+        }
+        let key = self as *const Self as usize;
+        let t = event.timestamp.unwrap_or_else(SystemTime::now);
+        let (mut step, mut start) = state.sequence(key).unwrap_or((0, t));
+        if let Some(timeout) = seq.timeout {
+            if t.duration_since(start).unwrap_or_default() > timeout {
+                step = 0;
+            }
+        }
+        if step > 0 && seq.steps[step].matches(event) {
+            step += 1;
+        }
+        else if seq.steps[0].matches(event) {
+            step = 1;
+            start = t;
+        }
+        else {
+            step = 0;
+        }
+        if step == 0 || step == seq.steps.len() {
+            state.set_sequence(key, None);
+        }
+        else {
+            state.set_sequence(key, Some((step, start)));
+        }
+        step == seq.steps.len()
+    }
+
+    // run list triggered by a note event on a chord config: run on press, release on release
+    pub fn match_chord(&self, event: &Event, state: &mut DeviceState) -> Option<&[RunConfig]> {
+        let chord = self.chord.as_ref()?;
+        if !self.channel.contains(&event.channel) || !chord.notes.contains(&event.id) {
+            return None;
+        }
+        let key = (self as *const Self as usize, event.channel);
+        let active = state.chord_active(key);
+        if event.is_note_release() && active {
+            state.set_chord_active(key, false);
+            return Some(&chord.release);
+        }
+        if !event.is_note_press() || active {
+            return None;
+        }
+        // all notes held, and pressed within tolerance
+        let (mut first, mut last) = (None, None);
+        for note in &chord.notes {
+            let t = state.held(event.channel, note)?;
+            first = Some(first.map_or(t, |x: SystemTime| x.min(t)));
+            last = Some(last.map_or(t, |x: SystemTime| x.max(t)));
+        }
+        if chord.r#match == ChordMatch::Exact && state.held_count(event.channel) != chord.notes.len() {
+            return None;
+        }
+        if let (Some(tolerance), Some(first), Some(last)) = (chord.tolerance, first, last) {
+            if last.duration_since(first).unwrap_or_default() > tolerance {
+                return None;
+            }
+        }
+        state.set_chord_active(key, true);
+        Some(&self.run)
+    }
+}
+
+// event configs of serializers, one per event type
+pub fn expand_events(v: Vec<EventConfigSerializer>) -> Result<Vec<EventConfig>, Error> {
+    let mut r = Vec::with_capacity(v.len());
+    for x in v {
+        match x.r#type.as_ref().map(|t| t.types()) {
+            Some(mut types) if types.len() > 1 => {
+                // a NoteOn gesture already follows the NoteOff releases of its note
+                if x.gesture.is_some() && types.contains(&EventType::NoteOn) {
+                    types.retain(|t| *t != EventType::NoteOff);
+                }
+                for t in types {
+                    let mut x = x.clone();
+                    x.r#type = Some(t.into());
+                    r.push(EventConfig::try_from(x)?);
+                }
+            }
+            _ => r.push(EventConfig::try_from(x)?),
+        }
+    }
+    Ok(r)
 }
 
 impl TryFrom<EventConfigSerializer> for EventConfig {
     type Error = crate::Error;
     fn try_from(v: EventConfigSerializer) -> Result<Self, Self::Error> {
+        if v.on.is_none() && (v.threshold.is_some() || v.hysteresis.is_some()) {
+            return Err(Error::from(ConfigError::TriggerArgsWithoutOn));
+        }
+        if v.chord.is_none() && (v.chord_match.is_some() || v.tolerance.is_some() || v.release.is_some()) {
+            return Err(Error::from(ConfigError::ChordArgsWithoutChord));
+        }
+        if v.sequence.is_none() && v.timeout.is_some() {
+            return Err(Error::from(ConfigError::TimeoutWithoutSequence));
+        }
+        if v.queue_length == Some(0) {
+            return Err(Error::from(ConfigError::QueueLength));
+        }
+        if v.page.is_none() && v.page_mode.is_some() {
+            return Err(Error::from(ConfigError::PageModeWithoutPage));
+        }
+        // multiple types are split by expand_events
+        let r#type = v.r#type.as_ref().and_then(|x| x.types().first().copied());
+        let (r#type, chord, sequence) = match (r#type, v.chord, v.sequence) {
+            (Some(_), Some(_), _      ) => return Err(Error::IncompatibleArgs("type","chord")),
+            (Some(_), None,    Some(_)) => return Err(Error::IncompatibleArgs("type","sequence")),
+            (None,    Some(_), Some(_)) => return Err(Error::IncompatibleArgs("chord","sequence")),
+            (None,    None,    None   ) => return Err(Error::from(ConfigError::EventMissingType)),
+            (Some(t), None,    None   ) => (t, None, None),
+            (None,    Some(c), None   ) => (EventType::NoteOn, Some(Chord {
+                notes: c.into_iter().map(|x| x.0).collect(),
+                r#match: v.chord_match.unwrap_or(ChordMatch::Exact),
+                tolerance: v.tolerance.map(|x| x.unwrap()),
+                release: util::map_opt_tryfrom(v.release)?.unwrap_or_default(),
+            }), None),
+            (None,    None,    Some(s)) => {
+                let steps: Vec<SequenceStep> = util::map_tryfrom(s)?;
+                // event values are the ones of the last step
+                let t = steps.last().ok_or(ConfigError::EmptySequence)?.r#type;
+                (t, None, Some(Sequence {
+                    steps,
+                    timeout: v.timeout.map(|x| x.unwrap()),
+                }))
+            }
+        };
+        if v.gesture.is_some() && (chord.is_some() || sequence.is_some() || !matches!(r#type, EventType::NoteOn | EventType::Controller)) {
+            return Err(Error::from(ConfigError::GestureType));
+        }
+        // gestures are made of presses and releases whatever their values
+        if v.gesture.is_some() {
+            if v.value.is_some() {
+                return Err(Error::IncompatibleArgs("gesture", "value"));
+            }
+            if v.on.is_some() {
+                return Err(Error::IncompatibleArgs("gesture", "on"));
+            }
+        }
+        if v.relative.is_some() && (r#type != EventType::Controller || chord.is_some() || sequence.is_some()) {
+            return Err(Error::from(ConfigError::RelativeType));
+        }
+        if v.relative.is_some() {
+            let remap = [("remap", v.remap.is_some()), ("curve", v.curve.is_some()), ("invert", v.invert.is_some()), ("remaps", v.remaps.is_some())];
+            if let Some((key, _)) = remap.into_iter().find(|x| x.1) {
+                return Err(Error::IncompatibleArgs("relative", key));
+            }
+        }
+        if v.accumulate.is_some() && v.relative.is_none() {
+            return Err(Error::from(ConfigError::AccumulateWithoutRelative));
+        }
+        let steps = match (v.toggle, v.cycle) {
+            (Some(_), Some(_)) => return Err(Error::IncompatibleArgs("toggle","cycle")),
+            (Some(_), None) if v.run.is_some() => return Err(Error::IncompatibleArgs("run","toggle")),
+            (None, Some(_)) if v.run.is_some() => return Err(Error::IncompatibleArgs("run","cycle")),
+            (Some(t), None) => Some(vec![
+                ("on".to_string(),  util::map_opt_tryfrom(t.on)?.unwrap_or_default()),
+                ("off".to_string(), util::map_opt_tryfrom(t.off)?.unwrap_or_default()),
+            ]),
+            (None, Some(c)) => {
+                if c.is_empty() {
+                    return Err(Error::from(ConfigError::EmptyCycle));
+                }
+                Some(c.into_iter().enumerate()
+                    .map(|(i, x)| Ok((i.to_string(), util::map_tryfrom(x)?)))
+                    .collect::<Result<Vec<_>, Error>>()?)
+            }
+            (None, None) if v.persist.is_some() => return Err(Error::from(ConfigError::PersistWithoutCycle)),
+            (None, None) => None,
+        };
+        let src = Range::new(r#type.min_value() as f64, r#type.max_value() as f64);
+        let float = v.float.unwrap_or(false);
+        let remaps = v.remaps.unwrap_or_default().into_iter().map(|(name, x)| {
+            let (remap, float) = match x {
+                RemapSerializer::Range(range) => (Remapper::new(src, range), float),
+                RemapSerializer::Full(x) => (
+                    Remapper::new(src, x.range.unwrap_or(src)).with_curve(x.curve.unwrap_or_default(), x.invert.unwrap_or(false)),
+                    x.float.unwrap_or(float),
+                ),
+            };
+            check_remap(&remap)?;
+            Ok(NamedRemap {
+                key: format!("value_{}", name),
+                remap,
+                float,
+            })
+        }).collect::<Result<Vec<NamedRemap>, Error>>()?;
+        #[cfg(not(feature = "script"))]
+        if v.script.is_some() {
+            return Err(Error::from(ConfigError::ScriptDisabled));
+        }
         let r = EventConfig {
-            run: util::map_tryfrom(v.run)?,
-            r#type: v.r#type,
-            channel: match v.r#type.has_channel() {
+            run: util::map_opt_tryfrom(v.run)?.unwrap_or_default(),
+            r#type,
+            channel: match r#type.has_channel() {
                 true  => v.channel.unwrap_or_else(|| CHANNEL_DEFAULT_MAP.clone()),
                 false => NULL_DEFAULT_MAP.clone(),
             },
-            id: match v.r#type.has_id() {
+            id: match r#type.has_id() && chord.is_none() && sequence.is_none() {
                 true  => v.id.unwrap_or_else(|| ID_DEFAULT_MAP.clone()),
                 false => NULL_DEFAULT_MAP.clone(),
             },
-            remap: v.remap.map(|x| Remapper::new(Range::new(v.r#type.min_value() as f64, v.r#type.max_value() as f64), x )),
-            float: v.float.unwrap_or(false),
+            remap: {
+                // a curve without remap keeps the source range
+                match (v.remap, v.curve.is_some() || v.invert.is_some()) {
+                    (Some(x), _) => Some(Remapper::new(src, x)),
+                    (None, true) => Some(Remapper::new(src, src)),
+                    (None, false) => None,
+                }.map(|x| x.with_curve(v.curve.unwrap_or_default(), v.invert.unwrap_or(false)))
+            },
+            float,
             value: v.value,
+            trigger: v.on,
+            threshold: v.threshold.unwrap_or(((r#type.max_value()+1)/2) as u16),
+            hysteresis: v.hysteresis.unwrap_or(0),
+            chord,
+            sequence,
+            gesture: v.gesture,
+            page: v.page,
+            page_mode: v.page_mode.unwrap_or_default(),
+            cycle: steps.map(|steps| Cycle {
+                steps,
+                persist: v.persist,
+            }),
+            queue_length: v.queue_length,
+            overflow: v.overflow,
+            interval: v.interval.map(|x| x.unwrap()),
+            group: v.group,
+            relative: v.relative,
+            accumulate: v.accumulate,
+            remaps,
+            priority: v.priority.unwrap_or(0),
+            r#final: v.r#final.unwrap_or(false),
+            vars: util::map_opt_tryfrom(v.vars)?.unwrap_or_default(),
+            when: v.when.map(When::try_from).transpose()?,
+            pipe: v.pipe.map(|x| x.into_vec()).unwrap_or_default(),
+            #[cfg(feature = "script")]
+            script: v.script.as_deref().map(str::parse).transpose()?,
         };
         if let Some(remap) = &r.remap {
-            let range = remap.src();
-            if range.start() < i64::MIN as f64 { return Err(Self::Error::RemapTooLow(range.start())) }
-            if range.end() > i64::MAX as f64  { return Err(Self::Error::RemapTooBig(range.end())) }
+            check_remap(remap)?;
         }
         Ok(r)
     }
 }
+
+// remapped values have to fit in integers
+fn check_remap(remap: &Remapper<f64>) -> Result<(), Error> {
+    let range = remap.dst();
+    let (low, high) = (range.start().min(range.end()), range.start().max(range.end()));
+    if low < i64::MIN as f64 { return Err(Error::RemapTooLow(low)) }
+    if high > i64::MAX as f64 { return Err(Error::RemapTooBig(high)) }
+    Ok(())
+}
+
+impl TryFrom<SequenceStepSerializer> for SequenceStep {
+    type Error = crate::Error;
+    fn try_from(v: SequenceStepSerializer) -> Result<Self, Self::Error> {
+        Ok(SequenceStep {
+            r#type: v.r#type,
+            channel: match v.r#type.has_channel() {
+                true  => v.channel.unwrap_or_else(|| CHANNEL_DEFAULT_MAP.clone()),
+                false => NULL_DEFAULT_MAP.clone(),
+            },
+            id: match v.r#type.has_id() {
+                true  => v.id.unwrap_or_else(|| ID_DEFAULT_MAP.clone()),
+                false => NULL_DEFAULT_MAP.clone(),
+            },
+            // by default only presses are steps
+            value: v.value.unwrap_or_else(|| match v.r#type {
+                EventType::NoteOn |
+                EventType::Controller |
+                EventType::PolyphonicKeyPressure
+                    => TRIGGER_NOTE_DEFAULT_MAP.clone(),
+                EventType::NoteOff |
+                EventType::ChannelPressure
+                    => TRIGGER_U8_DEFAULT_MAP.clone(),
+                EventType::PitchBend
+                    => TRIGGER_U16_DEFAULT_MAP.clone(),
+                _ => TRIGGER_NULL_DEFAULT_MAP.clone(),
+            }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(yaml: &str) -> Result<EventConfig, Error> {
+        EventConfig::try_from(serde_yaml::from_str::<EventConfigSerializer>(yaml).unwrap())
+    }
+
+    #[test]
+    fn relative_decode() {
+        let cases = [
+            (Relative::TwosComplement, [(0, 0), (1, 1), (63, 63), (64, -64), (127, -1)]),
+            (Relative::SignMagnitude, [(0, 0), (1, 1), (63, 63), (65, -1), (127, -63)]),
+            (Relative::BinaryOffset, [(64, 0), (65, 1), (127, 63), (63, -1), (0, -64)]),
+        ];
+        for (r, values) in cases {
+            for (v, delta) in values {
+                assert_eq!(r.decode(v), delta, "{:?} {}", r, v);
+            }
+        }
+    }
+
+    #[test]
+    fn relative_decode_masks_high_bits() {
+        assert_eq!(Relative::TwosComplement.decode(0x81), 1);
+        assert_eq!(Relative::BinaryOffset.decode(0xc1), 1);
+    }
+
+    #[test]
+    fn trigger_args() {
+        assert!(event("{ type: Controller, on: rise, threshold: 10, hysteresis: 2 }").is_ok());
+        assert!(event("{ type: Controller, threshold: 10 }").is_err());
+        assert!(event("{ type: Controller, hysteresis: 2 }").is_err());
+    }
+
+    #[test]
+    fn chord_args() {
+        assert!(event("{ chord: [ C4, E4 ], chord_match: superset, tolerance: 100ms, release: [ cmd: x ] }").is_ok());
+        assert!(event("{ type: NoteOn, chord_match: superset }").is_err());
+        assert!(event("{ type: NoteOn, tolerance: 100ms }").is_err());
+        assert!(event("{ type: NoteOn, release: [ cmd: x ] }").is_err());
+    }
+
+    #[test]
+    fn sequence_args() {
+        assert!(event("{ sequence: [ { type: NoteOn, id: 1 } ], timeout: 1s }").is_ok());
+        assert!(event("{ type: NoteOn, timeout: 1s }").is_err());
+    }
+
+    #[test]
+    fn page_args() {
+        assert!(event("{ type: NoteOn, page: shift, page_mode: hold }").is_ok());
+        assert!(event("{ type: NoteOn, page_mode: hold }").is_err());
+    }
+
+    #[test]
+    fn queue_length() {
+        assert!(event("{ type: NoteOn, queue_length: 1 }").is_ok());
+        assert!(event("{ type: NoteOn, queue_length: 0 }").is_err());
+    }
+
+    #[test]
+    fn gesture_args() {
+        assert!(event("{ type: NoteOn, gesture: tap }").is_ok());
+        assert!(event("{ type: NoteOn, gesture: tap, value: 1-127 }").is_err());
+        assert!(event("{ type: Controller, gesture: tap, on: rise }").is_err());
+    }
+}
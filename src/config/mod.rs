@@ -2,14 +2,19 @@ pub mod event;
 pub mod device;
 pub mod run;
 pub mod serializer;
+pub mod var;
+pub mod when;
+pub mod pipe;
 
 use serializer::ConfigSerializer;
 
+use std::collections::BTreeMap;
 use std::str::FromStr;
 
+use crate::error::ConfigError;
 use crate::util;
 
-pub use device::DeviceConfig;
+pub use device::{DeviceConfig,TimerConfig};
 pub use event::EventConfig;
 pub use run::RunConfig;
 pub type EventEnvMap = serializer::EventEnvSerializer;
@@ -19,16 +24,63 @@ pub struct Config {
     pub log: bool,
     pub driver: Option<crate::midi::MidiDriver>,
     pub devices: Vec<DeviceConfig>,
+    // global variables and their initial values
+    pub vars: BTreeMap<String, i64>,
+    // commands run on first load, on reload, before exit and on error
+    pub on_start: Vec<RunConfig>,
+    pub on_reload: Vec<RunConfig>,
+    pub on_exit: Vec<RunConfig>,
+    pub on_error: Vec<RunConfig>,
+    // run lists repeated while the config is running
+    pub timers: Vec<TimerConfig>,
+    // pipes shared by all devices while the config is running
+    pub pipes: Vec<pipe::PipeConfig>,
 }
 
 impl TryFrom<ConfigSerializer> for Config {
     type Error = crate::Error;
     fn try_from(v: ConfigSerializer) -> Result<Self, Self::Error> {
-        Ok(Config {
+        let r = Config {
             log: v.log_devices.unwrap_or(false),
+            vars: v.vars.unwrap_or_default(),
+            on_start:  util::map_opt_tryfrom(v.on_start)?.unwrap_or_default(),
+            on_reload: util::map_opt_tryfrom(v.on_reload)?.unwrap_or_default(),
+            on_exit:   util::map_opt_tryfrom(v.on_exit)?.unwrap_or_default(),
+            on_error:  util::map_opt_tryfrom(v.on_error)?.unwrap_or_default(),
+            timers:    util::map_opt_tryfrom(v.timers)?.unwrap_or_default(),
+            pipes:     pipe::map_pipes(v.pipes)?,
             driver: v.driver,
-            devices: util::map_tryfrom(v.devices)?,
-        })
+            devices: {
+                let shared = v.shared_events.unwrap_or_default();
+                let mut devices = v.devices;
+                // append shared events to the devices including them
+                for d in &mut devices {
+                    for name in d.include.take().unwrap_or_default() {
+                        let events = shared.get(&name).ok_or(ConfigError::UnknownSharedEvents(name))?;
+                        d.events.get_or_insert_with(Vec::new).extend(events.iter().cloned());
+                    }
+                }
+                util::map_tryfrom(devices)?
+            },
+        };
+        // check variables and pipes are declared by their device or globally
+        for d in &r.devices {
+            let pages = d.pages.iter().flat_map(|x| x.events.iter());
+            for e in d.events.iter().flatten().chain(pages) {
+                let conditions = e.when.iter().flat_map(|x| x.var.iter().map(|x| &x.name));
+                for name in e.vars.iter().map(|x| &x.name).chain(conditions) {
+                    if !d.vars.contains_key(name) && !r.vars.contains_key(name) {
+                        return Err(ConfigError::UnknownVar(name.clone()).into());
+                    }
+                }
+                for name in &e.pipe {
+                    if !d.pipes.iter().chain(&r.pipes).any(|x| &x.name == name) {
+                        return Err(ConfigError::UnknownPipe(name.clone()).into());
+                    }
+                }
+            }
+        }
+        Ok(r)
     }
 }
 
@@ -36,7 +88,7 @@ impl TryFrom<&[u8]> for Config {
     type Error = crate::Error;
     fn try_from(dat: &[u8]) -> Result<Self, Self::Error> {
         let c: ConfigSerializer = serde_yaml::from_slice(dat)?;
-        Ok(Config::try_from(c)?)
+        Config::try_from(c)
     }
 }
 
@@ -44,6 +96,6 @@ impl FromStr for Config {
     type Err = crate::Error;
     fn from_str(dat: &str) -> Result<Self, Self::Err> {
         let c: ConfigSerializer = serde_yaml::from_str(dat)?;
-        Ok(Config::try_from(c)?)
+        Config::try_from(c)
     }
 }
@@ -0,0 +1,51 @@
+use crate::error::ConfigError;
+use crate::util::Template;
+
+use super::serializer::PipeConfigSerializer;
+
+use serde::Deserialize;
+
+// When a dead pipe process is started again, checked on the next write
+#[derive(Deserialize,Debug,Clone,Copy,PartialEq,Eq,Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Restart {
+    Never,
+    #[default]
+    OnFailure,
+    Always,
+}
+
+// Long-lived process fed with one line per event on its stdin
+#[derive(Debug,Clone)]
+pub struct PipeConfig {
+    pub name: String,
+    pub args: Vec<String>,
+    // line format with placeholders like args, JSON object of the env when None
+    pub template: Option<Template>,
+    pub restart: Restart,
+}
+
+impl TryFrom<(String, PipeConfigSerializer)> for PipeConfig {
+    type Error = crate::Error;
+    fn try_from((name, v): (String, PipeConfigSerializer)) -> Result<Self, Self::Error> {
+        let args = match (v.args, v.cmd) {
+            (Some(_), Some(_)) => return Err(crate::Error::IncompatibleArgs("args", "cmd")),
+            (Some(a), None) if !a.is_empty() => a,
+            (None, Some(c)) => crate::run::cross_shell(&c),
+            _ => return Err(crate::Error::from(ConfigError::RunMissingArgs)),
+        };
+        Ok(PipeConfig {
+            name,
+            args,
+            template: v.template.map(|x| x.parse().unwrap()),
+            restart: v.restart.unwrap_or_default(),
+        })
+    }
+}
+
+pub fn map_pipes<I>(v: Option<I>) -> Result<Vec<PipeConfig>, crate::Error>
+where
+    I: IntoIterator<Item = (String, PipeConfigSerializer)>,
+{
+    v.into_iter().flatten().map(PipeConfig::try_from).collect()
+}
@@ -1,37 +1,141 @@
-use std::collections::HashMap;
-use std::process::{Command, ExitStatus};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use super::serializer::RunConfigSerializer;
 use super::EventEnvMap;
+use crate::error::ConfigError;
+use crate::util::Template;
+
+// time given to a command to exit after SIGTERM on timeout
+const KILL_TIMEOUT: Duration = Duration::from_secs(1);
 
 #[derive(Debug,Clone)]
 pub struct RunConfig {
     pub args: Vec<String>,
+    // placeholders of args, empty if none has any
+    pub templates: Vec<Template>,
     pub envconf: Option<EventEnvMap>,
     pub detach: bool,
+    pub cwd: Option<PathBuf>,
+    // static env, set over the event values
+    pub env: BTreeMap<String, String>,
+    // don't inherit the env of rmidimap
+    pub clear_env: bool,
+    // text written to stdin, with placeholders
+    pub stdin: Option<Template>,
+    // SIGTERM once elapsed, then SIGKILL after KILL_TIMEOUT
+    pub timeout: Option<Duration>,
+    pub nice: Option<i32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
 }
 
 impl RunConfig {
     pub fn run(&self, env: HashMap<&str, String>) -> Result<Option<ExitStatus>, std::io::Error> {
-        let mut c = Command::new(&self.args[0]);
-        if self.args.len() > 1 {
-            c.args(&self.args[1..]);
+        let args: Vec<String> = match self.templates.is_empty() {
+            true => self.args.clone(),
+            false => self.templates.iter().map(|x| x.render(&env)).collect(),
+        };
+        let stdin = self.stdin.as_ref().map(|x| x.render(&env));
+        let mut c = Command::new(&args[0]);
+        if args.len() > 1 {
+            c.args(&args[1..]);
+        }
+        if self.clear_env {
+            c.env_clear();
         }
         c.envs(env);
+        c.envs(&self.env);
+        if let Some(d) = &self.cwd {
+            c.current_dir(d);
+        }
+        if let Some(g) = self.gid {
+            c.gid(g);
+        }
+        if let Some(u) = self.uid {
+            c.uid(u);
+        }
+        if let Some(n) = self.nice {
+            // SAFETY: setpriority is async-signal-safe
+            unsafe {
+                c.pre_exec(move || match libc::setpriority(libc::PRIO_PROCESS, 0, n) {
+                    -1 => Err(std::io::Error::last_os_error()),
+                    _ => Ok(()),
+                });
+            }
+        }
+        if self.timeout.is_some() {
+            // own process group, so commands started by the child are signaled too
+            c.process_group(0);
+        }
+        if stdin.is_some() {
+            c.stdin(Stdio::piped());
+        }
+        let timeout = self.timeout;
+        let exec = move || -> Result<ExitStatus, std::io::Error> {
+            let mut child = c.spawn()?;
+            if let (Some(text), Some(mut input)) = (stdin, child.stdin.take()) {
+                // written aside so a command not reading its input can't block
+                thread::spawn(move || input.write_all(text.as_bytes()));
+            }
+            wait(child, timeout)
+        };
         if self.detach {
-            std::thread::spawn(move || {
-                c.status()
-            });
+            thread::spawn(exec);
             Ok(None)
         } else {
-            c.status().map(|v| Some(v))
+            exec().map(Some)
+        }
+    }
+}
+
+// signal the process group of a child started with process_group(0)
+fn signal_group(child: &Child, sig: libc::c_int) {
+    // SAFETY: kill has no memory effect, the group lives as long as the unwaited child
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), sig);
+    }
+}
+
+// wait for the child to exit, terminating its process group once timeout has elapsed
+fn wait(mut child: Child, timeout: Option<Duration>) -> Result<ExitStatus, std::io::Error> {
+    let timeout = match timeout {
+        Some(t) => t,
+        None => return child.wait(),
+    };
+    let start = Instant::now();
+    let step = Duration::from_millis(10);
+    let mut term: Option<Instant> = None;
+    loop {
+        if let Some(s) = child.try_wait()? {
+            return Ok(s);
         }
+        match term {
+            None if start.elapsed() >= timeout => {
+                eprintln!("WARN: command timed out after {:?}, terminating it", timeout);
+                signal_group(&child, libc::SIGTERM);
+                term = Some(Instant::now());
+            }
+            Some(t) if t.elapsed() >= KILL_TIMEOUT => {
+                signal_group(&child, libc::SIGKILL);
+                return child.wait();
+            }
+            _ => (),
+        }
+        thread::sleep(step);
     }
 }
 
 impl TryFrom<RunConfigSerializer> for RunConfig {
     type Error = crate::Error;
     fn try_from(v: RunConfigSerializer) -> Result<Self, Self::Error> {
+        // placeholders are only expanded in args, cmd is left to the shell
+        let expand = v.args.is_some();
         let args = if v.args.is_some() {
             v.args.unwrap()
         }
@@ -39,13 +143,40 @@ impl TryFrom<RunConfigSerializer> for RunConfig {
             crate::run::cross_shell(v.cmd.as_ref().unwrap())
         }
         else {
-            return Err(crate::Error::from(crate::error::ConfigError::RunMissingArgs));
+            return Err(crate::Error::from(ConfigError::RunMissingArgs));
+        };
+        let timeout = v.timeout.map(|x| x.unwrap());
+        if timeout == Some(Duration::ZERO) {
+            return Err(crate::Error::from(ConfigError::RunTimeout));
+        }
+        if let Some(n) = v.nice.filter(|x| !(-20..=19).contains(x)) {
+            return Err(crate::Error::from(ConfigError::InvalidNice(n)));
+        }
+        // nice is set once the user is changed, when lowering it is no longer allowed
+        if v.nice.is_some_and(|x| x < 0) && v.uid.is_some() {
+            return Err(crate::Error::from(ConfigError::NegativeNiceUid));
+        }
+        let templates: Vec<Template> = match expand {
+            true => args.iter().map(|x| x.parse().unwrap()).collect(),
+            false => Vec::new(),
         };
         Ok(
             RunConfig {
+                templates: match templates.iter().any(|x| x.has_fields()) {
+                    true => templates,
+                    false => Vec::new(),
+                },
                 args,
                 envconf: v.envconf,
                 detach: v.detach.unwrap_or(false),
+                cwd: v.cwd.map(PathBuf::from),
+                env: v.env.unwrap_or_default(),
+                clear_env: v.clear_env.unwrap_or(false),
+                stdin: v.stdin.map(|x| x.parse().unwrap()),
+                timeout,
+                nice: v.nice,
+                uid: v.uid,
+                gid: v.gid,
             }
         )
     }
@@ -1,6 +1,8 @@
+use std::collections::BTreeMap;
 use std::time::Duration;
 
-use super::{RunConfigSerializer,EventConfigSerializer};
+use super::{RunConfigSerializer,EventConfigSerializer,PipeConfigSerializer};
+use crate::config::device::{Overflow,MatchMode};
 
 use duration_str::deserialize_duration;
 use serde::Deserialize;
@@ -29,9 +31,44 @@ pub struct DeviceConfigSerializer {
     pub addr: Option<String>,
     pub connect: Option<Vec<RunConfigSerializer>>,
     pub disconnect: Option<Vec<RunConfigSerializer>>,
+    pub unmatched: Option<Vec<RunConfigSerializer>>,
     pub events: Option<Vec<EventConfigSerializer>>,
+    pub include: Option<Vec<String>>,
+    pub pages: Option<BTreeMap<String, PageConfigSerializer>>,
+    pub default_page: Option<String>,
     pub max_connections: Option<u32>,
     pub queue_length: Option<usize>,
+    pub overflow: Option<Overflow>,
     pub interval: Option<DurationWrapper>,
     pub log_events: Option<bool>,
+    pub r#match: Option<MatchMode>,
+    pub vars: Option<BTreeMap<String, i64>>,
+    pub timers: Option<Vec<TimerConfigSerializer>>,
+    pub idle: Option<IdleConfigSerializer>,
+    pub sensing_timeout: Option<DurationWrapper>,
+    pub sensing_lost: Option<Vec<RunConfigSerializer>>,
+    pub sensing_resumed: Option<Vec<RunConfigSerializer>>,
+    pub sensing_disconnect: Option<bool>,
+    pub pipes: Option<BTreeMap<String, PipeConfigSerializer>>,
+}
+
+#[derive(Deserialize,Debug,Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PageConfigSerializer {
+    pub events: Vec<EventConfigSerializer>,
+}
+
+#[derive(Deserialize,Debug,Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TimerConfigSerializer {
+    pub interval: DurationWrapper,
+    pub run: Vec<RunConfigSerializer>,
+}
+
+#[derive(Deserialize,Debug,Clone)]
+#[serde(deny_unknown_fields)]
+pub struct IdleConfigSerializer {
+    pub after: DurationWrapper,
+    pub run: Option<Vec<RunConfigSerializer>>,
+    pub resume: Option<Vec<RunConfigSerializer>>,
 }
@@ -1,17 +1,127 @@
-use super::RunConfigSerializer;
+use std::collections::BTreeMap;
+
+use super::{RunConfigSerializer,DurationWrapper,VarActionSerializer,WhenSerializer,OneOrMany};
+use crate::config::device::Overflow;
+use crate::config::event::{Trigger,ChordMatch,PageMode,Relative};
 use crate::event::EventType;
-use crate::util::{SmartSet,Range};
+use crate::gesture::Gesture;
+use crate::util::{SmartSet,Range,Note,Curve};
 
 use serde::Deserialize;
 
 #[derive(Deserialize,Debug,Clone)]
 #[serde(deny_unknown_fields)]
 pub struct EventConfigSerializer {
-    pub run: Vec<RunConfigSerializer>,
-    pub r#type: EventType,
+    pub run: Option<Vec<RunConfigSerializer>>,
+    pub r#type: Option<TypeSerializer>,
     pub channel: Option<SmartSet<u8>>,
     pub id: Option<SmartSet<u8>>,
     pub remap: Option<Range<f64>>,
     pub float: Option<bool>,
     pub value: Option<SmartSet<u16>>,
+    pub on: Option<Trigger>,
+    pub threshold: Option<u16>,
+    pub hysteresis: Option<u16>,
+    pub chord: Option<Vec<Note>>,
+    pub chord_match: Option<ChordMatch>,
+    pub tolerance: Option<DurationWrapper>,
+    pub release: Option<Vec<RunConfigSerializer>>,
+    pub sequence: Option<Vec<SequenceStepSerializer>>,
+    pub timeout: Option<DurationWrapper>,
+    pub gesture: Option<Gesture>,
+    pub page: Option<String>,
+    pub page_mode: Option<PageMode>,
+    pub toggle: Option<ToggleSerializer>,
+    pub cycle: Option<Vec<Vec<RunConfigSerializer>>>,
+    pub persist: Option<String>,
+    pub queue_length: Option<usize>,
+    pub overflow: Option<Overflow>,
+    pub interval: Option<DurationWrapper>,
+    pub group: Option<String>,
+    pub relative: Option<Relative>,
+    pub accumulate: Option<Range<i64>>,
+    pub curve: Option<Curve>,
+    pub invert: Option<bool>,
+    pub remaps: Option<BTreeMap<String, RemapSerializer>>,
+    pub priority: Option<i32>,
+    pub r#final: Option<bool>,
+    pub vars: Option<Vec<VarActionSerializer>>,
+    pub when: Option<WhenSerializer>,
+    pub pipe: Option<OneOrMany<String>>,
+    pub script: Option<String>,
+}
+
+#[derive(Deserialize,Debug,Clone)]
+#[serde(untagged)]
+pub enum RemapSerializer {
+    Range(Range<f64>),
+    Full(RemapConfigSerializer),
+}
+
+#[derive(Deserialize,Debug,Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RemapConfigSerializer {
+    pub range: Option<Range<f64>>,
+    pub curve: Option<Curve>,
+    pub invert: Option<bool>,
+    pub float: Option<bool>,
+}
+
+#[derive(Deserialize,Debug,Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ToggleSerializer {
+    pub on: Option<Vec<RunConfigSerializer>>,
+    pub off: Option<Vec<RunConfigSerializer>>,
+}
+
+// Event type, or Note for both NoteOn and NoteOff
+#[derive(Deserialize,Debug,Clone,Copy)]
+pub enum TypeName {
+    Note,
+    #[serde(untagged)]
+    Type(EventType),
+}
+
+#[derive(Deserialize,Debug,Clone)]
+#[serde(untagged)]
+pub enum TypeSerializer {
+    One(TypeName),
+    Many(Vec<TypeName>),
+}
+
+impl TypeSerializer {
+    pub fn types(&self) -> Vec<EventType> {
+        let names = match self {
+            TypeSerializer::One(x) => std::slice::from_ref(x),
+            TypeSerializer::Many(x) => &x[..],
+        };
+        let mut r = Vec::new();
+        for name in names {
+            let types = match name {
+                TypeName::Note => &[EventType::NoteOn, EventType::NoteOff][..],
+                TypeName::Type(t) => std::slice::from_ref(t),
+            };
+            for t in types {
+                if !r.contains(t) {
+                    r.push(*t);
+                }
+            }
+        }
+        r
+    }
+}
+
+impl From<EventType> for TypeSerializer {
+    fn from(t: EventType) -> Self {
+        TypeSerializer::One(TypeName::Type(t))
+    }
+}
+
+#[derive(Deserialize,Debug,Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SequenceStepSerializer {
+    pub r#type: EventType,
+    pub channel: Option<SmartSet<u8>>,
+    pub id: Option<SmartSet<u8>>,
+    pub value: Option<SmartSet<u16>>,
 }
@@ -10,4 +10,7 @@ pub struct EventEnvSerializer {
     pub rawvalue: Option<String>,
     pub timestamp: Option<String>,
     pub value: Option<String>,
+    pub state: Option<String>,
+    pub delta: Option<String>,
+    pub tick: Option<String>,
 }
\ No newline at end of file
@@ -2,11 +2,19 @@ pub mod event;
 pub mod device;
 pub mod run;
 pub mod eventenv;
+pub mod var;
+pub mod when;
+pub mod pipe;
 
-pub use device::DeviceConfigSerializer;
-pub use event::EventConfigSerializer;
+pub use device::{DeviceConfigSerializer,PageConfigSerializer,TimerConfigSerializer,IdleConfigSerializer,DurationWrapper};
+pub use event::{EventConfigSerializer,SequenceStepSerializer,ToggleSerializer,RemapSerializer,RemapConfigSerializer,TypeSerializer};
 pub use run::RunConfigSerializer;
 pub use eventenv::EventEnvSerializer;
+pub use var::VarActionSerializer;
+pub use when::{WhenSerializer,CcConditionSerializer};
+pub use pipe::PipeConfigSerializer;
+
+use std::collections::BTreeMap;
 
 use serde::Deserialize;
 
@@ -15,5 +23,30 @@ use serde::Deserialize;
 pub struct ConfigSerializer {
     pub log_devices: Option<bool>,
     pub driver: Option<crate::midi::MidiDriver>,
+    pub shared_events: Option<BTreeMap<String, Vec<EventConfigSerializer>>>,
+    pub vars: Option<BTreeMap<String, i64>>,
+    pub on_start: Option<Vec<RunConfigSerializer>>,
+    pub on_reload: Option<Vec<RunConfigSerializer>>,
+    pub on_exit: Option<Vec<RunConfigSerializer>>,
+    pub on_error: Option<Vec<RunConfigSerializer>>,
+    pub timers: Option<Vec<TimerConfigSerializer>>,
+    pub pipes: Option<BTreeMap<String, PipeConfigSerializer>>,
     pub devices: Vec<DeviceConfigSerializer>,
 }
+
+// a value or a list of values
+#[derive(Deserialize,Debug,Clone)]
+#[serde(untagged)]
+pub enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> OneOrMany<T> {
+    pub fn into_vec(self) -> Vec<T> {
+        match self {
+            OneOrMany::One(x) => vec![x],
+            OneOrMany::Many(x) => x,
+        }
+    }
+}
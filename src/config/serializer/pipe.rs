@@ -0,0 +1,12 @@
+use crate::config::pipe::Restart;
+
+use serde::Deserialize;
+
+#[derive(Deserialize,Debug,Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PipeConfigSerializer {
+    pub args: Option<Vec<String>>,
+    pub cmd:  Option<String>,
+    pub template: Option<String>,
+    pub restart: Option<Restart>,
+}
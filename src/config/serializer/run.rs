@@ -1,12 +1,22 @@
-use super::EventEnvSerializer;
+use std::collections::BTreeMap;
 
-use serde::{Serialize,Deserialize};
+use super::{EventEnvSerializer,DurationWrapper};
 
-#[derive(Serialize,Deserialize,Debug,Clone)]
+use serde::Deserialize;
+
+#[derive(Deserialize,Debug,Clone)]
 #[serde(deny_unknown_fields)]
 pub struct RunConfigSerializer {
     pub args: Option<Vec<String>>,
     pub cmd:  Option<String>,
     pub envconf: Option<EventEnvSerializer>,
     pub detach: Option<bool>,
+    pub cwd: Option<String>,
+    pub env: Option<BTreeMap<String, String>>,
+    pub clear_env: Option<bool>,
+    pub stdin: Option<String>,
+    pub timeout: Option<DurationWrapper>,
+    pub nice: Option<i32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
 }
@@ -0,0 +1,15 @@
+use serde::Deserialize;
+
+#[derive(Deserialize,Debug,Clone)]
+#[serde(deny_unknown_fields)]
+pub struct VarActionSerializer {
+    pub set: Option<String>,
+    pub inc: Option<String>,
+    pub dec: Option<String>,
+    pub clamp: Option<String>,
+    pub reset: Option<String>,
+    pub value: Option<i64>,
+    pub by: Option<i64>,
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+}
@@ -0,0 +1,28 @@
+use serde::Deserialize;
+
+use super::OneOrMany;
+use crate::util::SmartSet;
+
+#[derive(Deserialize,Debug,Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WhenSerializer {
+    pub held: Option<Vec<HeldSerializer>>,
+    pub cc: Option<OneOrMany<CcConditionSerializer>>,
+    pub var: Option<OneOrMany<String>>,
+}
+
+// note number, or note name optionally prefixed by its type: "NoteOn 48", C3
+#[derive(Deserialize,Debug,Clone)]
+#[serde(untagged)]
+pub enum HeldSerializer {
+    Number(u8),
+    Name(String),
+}
+
+#[derive(Deserialize,Debug,Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CcConditionSerializer {
+    pub channel: Option<u8>,
+    pub id: u8,
+    pub value: Option<SmartSet<u16>>,
+}
@@ -0,0 +1,71 @@
+use crate::error::ConfigError;
+use crate::Error;
+
+use super::serializer::VarActionSerializer;
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum VarOp {
+    // set to a value, or to the event value
+    Set(Option<i64>),
+    Add(i64),
+    Clamp,
+    // back to initial value
+    Reset,
+}
+
+#[derive(Debug,Clone)]
+pub struct VarAction {
+    pub name: String,
+    pub op: VarOp,
+    // bounds of the result
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+}
+
+impl VarAction {
+    pub fn apply(&self, current: i64, initial: i64, value: i64) -> i64 {
+        let v = match self.op {
+            VarOp::Set(v) => v.unwrap_or(value),
+            VarOp::Add(n) => current.saturating_add(n),
+            VarOp::Clamp => current,
+            VarOp::Reset => initial,
+        };
+        let v = self.min.map_or(v, |m| v.max(m));
+        self.max.map_or(v, |m| v.min(m))
+    }
+}
+
+impl TryFrom<VarActionSerializer> for VarAction {
+    type Error = crate::Error;
+    fn try_from(v: VarActionSerializer) -> Result<Self, Self::Error> {
+        let ops = [
+            ("set", v.set), ("inc", v.inc), ("dec", v.dec), ("clamp", v.clamp), ("reset", v.reset),
+        ];
+        let mut ops = ops.into_iter().filter_map(|(op, name)| Some((op, name?)));
+        let (op, name) = ops.next().ok_or(ConfigError::VarActionMissing)?;
+        if let Some((other, _)) = ops.next() {
+            return Err(Error::IncompatibleArgs(op, other));
+        }
+        if v.value.is_some() && op != "set" {
+            return Err(Error::IncompatibleArgs("value", op));
+        }
+        if v.by.is_some() && op != "inc" && op != "dec" {
+            return Err(Error::IncompatibleArgs("by", op));
+        }
+        if op == "clamp" && v.min.is_none() && v.max.is_none() {
+            return Err(Error::from(ConfigError::ClampMissingBounds));
+        }
+        Ok(VarAction {
+            name,
+            op: match op {
+                "set" => VarOp::Set(v.value),
+                "inc" => VarOp::Add(v.by.unwrap_or(1)),
+                "dec" => VarOp::Add(-v.by.unwrap_or(1)),
+                "clamp" => VarOp::Clamp,
+                _ => VarOp::Reset,
+            },
+            min: v.min,
+            max: v.max,
+        })
+    }
+}
@@ -0,0 +1,170 @@
+use std::str::FromStr;
+
+use crate::error::ConfigError;
+use crate::event::{Event,EventType,event_to_index};
+use crate::state::DeviceState;
+use crate::util::SmartSet;
+use crate::util::note::parse_note;
+
+use super::serializer::{WhenSerializer,CcConditionSerializer};
+use super::serializer::when::HeldSerializer;
+
+use lazy_static::lazy_static;
+
+lazy_static! {
+    static ref CC_DEFAULT_VALUE: SmartSet<u16> = SmartSet::from_range(1, 127);
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+// comparison of a variable with a constant, example: mode == 2
+#[derive(Debug,Clone)]
+pub struct VarCondition {
+    pub name: String,
+    pub op: CmpOp,
+    pub value: i64,
+}
+
+// last value of a controller
+#[derive(Debug,Clone)]
+pub struct CcCondition {
+    // None for the channel of the event
+    pub channel: Option<u8>,
+    pub id: u8,
+    pub value: SmartSet<u16>,
+}
+
+// Conditions on device state, all of them have to be met
+#[derive(Debug,Clone,Default)]
+pub struct When {
+    // notes held on the channel of the event
+    pub held: Vec<u8>,
+    pub cc: Vec<CcCondition>,
+    pub var: Vec<VarCondition>,
+}
+
+impl When {
+    pub fn matches(&self, event: &Event, state: &DeviceState) -> bool {
+        self.held.iter().all(|n| state.held(event.channel, *n).is_some())
+        && self.cc.iter().all(|c| {
+            event_to_index(EventType::Controller, c.channel.unwrap_or(event.channel), c.id)
+                .and_then(|i| state.value(i))
+                .is_some_and(|v| c.value.contains(&v))
+        })
+        && self.var.iter().all(|c| state.var(&c.name).is_some_and(|v| c.matches(v)))
+    }
+}
+
+impl VarCondition {
+    pub fn matches(&self, v: i64) -> bool {
+        match self.op {
+            CmpOp::Eq => v == self.value,
+            CmpOp::Ne => v != self.value,
+            CmpOp::Lt => v < self.value,
+            CmpOp::Le => v <= self.value,
+            CmpOp::Gt => v > self.value,
+            CmpOp::Ge => v >= self.value,
+        }
+    }
+}
+
+impl FromStr for VarCondition {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ConfigError::InvalidCondition(s.to_string());
+        // two character operators first
+        let ops = [("==", CmpOp::Eq), ("!=", CmpOp::Ne), ("<=", CmpOp::Le), (">=", CmpOp::Ge), ("<", CmpOp::Lt), (">", CmpOp::Gt)];
+        let (name, op, value) = match ops.iter().find_map(|(x, op)| Some((s.find(x)?, x.len(), *op))) {
+            Some((i, len, op)) => (&s[..i], op, s[i+len..].trim().parse().map_err(|_| err())?),
+            // variable alone: non-zero
+            None => (s, CmpOp::Ne, 0),
+        };
+        let name = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(err());
+        }
+        Ok(VarCondition {
+            name: name.to_string(),
+            op,
+            value,
+        })
+    }
+}
+
+fn held_note(v: HeldSerializer) -> Result<u8, ConfigError> {
+    match v {
+        HeldSerializer::Number(n) if n < 128 => Ok(n),
+        HeldSerializer::Number(n) => Err(ConfigError::InvalidCondition(n.to_string())),
+        HeldSerializer::Name(s) => {
+            let t = s.trim();
+            let note = t.strip_prefix("NoteOn").or_else(|| t.strip_prefix("Note")).unwrap_or(t);
+            parse_note(note).map_err(|_| ConfigError::InvalidCondition(s.clone()))
+        }
+    }
+}
+
+impl From<CcConditionSerializer> for CcCondition {
+    fn from(v: CcConditionSerializer) -> Self {
+        CcCondition {
+            channel: v.channel,
+            id: v.id,
+            value: v.value.unwrap_or_else(|| CC_DEFAULT_VALUE.clone()),
+        }
+    }
+}
+
+impl TryFrom<WhenSerializer> for When {
+    type Error = ConfigError;
+    fn try_from(v: WhenSerializer) -> Result<Self, Self::Error> {
+        Ok(When {
+            held: v.held.unwrap_or_default().into_iter().map(held_note).collect::<Result<_, _>>()?,
+            cc: v.cc.map(|x| x.into_vec()).unwrap_or_default().into_iter().map(CcCondition::from).collect(),
+            var: v.var.map(|x| x.into_vec()).unwrap_or_default().iter().map(|x| x.parse()).collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cond(s: &str) -> (String, CmpOp, i64) {
+        let c: VarCondition = s.parse().unwrap();
+        (c.name, c.op, c.value)
+    }
+
+    #[test]
+    fn var_condition() {
+        assert_eq!(cond("mode == 2"), ("mode".to_string(), CmpOp::Eq, 2));
+        assert_eq!(cond("mode!=2"), ("mode".to_string(), CmpOp::Ne, 2));
+        assert_eq!(cond(" bank <= -1 "), ("bank".to_string(), CmpOp::Le, -1));
+        assert_eq!(cond("bank >= 3"), ("bank".to_string(), CmpOp::Ge, 3));
+        assert_eq!(cond("bank < 3"), ("bank".to_string(), CmpOp::Lt, 3));
+        assert_eq!(cond("bank > 3"), ("bank".to_string(), CmpOp::Gt, 3));
+        assert_eq!(cond(" shift "), ("shift".to_string(), CmpOp::Ne, 0));
+    }
+
+    #[test]
+    fn var_condition_invalid() {
+        for s in ["", "== 2", "mode = 2", "mode == x", "mode ==", "my mode", "a b > 1"] {
+            assert!(s.parse::<VarCondition>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn var_condition_matches() {
+        let c: VarCondition = "x >= 2".parse().unwrap();
+        assert!(!c.matches(1) && c.matches(2) && c.matches(3));
+        let c: VarCondition = "x".parse().unwrap();
+        assert!(!c.matches(0) && c.matches(-1));
+    }
+}
@@ -40,6 +40,10 @@ pub enum Error {
     NoArgument,
     #[error("pipe error")]
     Pipe,
+    #[error("pipe '{0}' is not running")]
+    PipeNotRunning(String),
+    #[error("thread panicked")]
+    Panic,
     #[error("unknown error")]
     Unknown,
 }
@@ -48,6 +52,68 @@ pub enum Error {
 pub enum ConfigError {
     #[error("run config is missing execution configuration, either \"args\" or \"cmd\" has to be specified")]
     RunMissingArgs,
+    #[error("event config is missing event type, either \"type\", \"chord\" or \"sequence\" has to be specified")]
+    EventMissingType,
+    #[error("\"threshold\" and \"hysteresis\" require \"on\"")]
+    TriggerArgsWithoutOn,
+    #[error("\"chord_match\", \"tolerance\" and \"release\" require \"chord\"")]
+    ChordArgsWithoutChord,
+    #[error("sequence has to contain at least one step")]
+    EmptySequence,
+    #[error("\"timeout\" requires \"sequence\"")]
+    TimeoutWithoutSequence,
+    #[error("invalid gesture '{0}', expected tap, double_tap, long_press or hold_repeat with an optional duration, example: long_press(1s)")]
+    InvalidGesture(String),
+    #[error("gestures are only supported on NoteOn and Controller events")]
+    GestureType,
+    #[error("unknown page '{0}'")]
+    UnknownPage(String),
+    #[error("page name 'base' is reserved for the base layer")]
+    ReservedPageName,
+    #[error("\"page_mode\" requires \"page\"")]
+    PageModeWithoutPage,
+    #[error("cycle has to contain at least one run list")]
+    EmptyCycle,
+    #[error("\"persist\" requires \"toggle\" or \"cycle\"")]
+    PersistWithoutCycle,
+    #[error("\"relative\" is only supported on Controller events")]
+    RelativeType,
+    #[error("\"accumulate\" requires \"relative\"")]
+    AccumulateWithoutRelative,
+    #[error("invalid curve '{0}', expected linear, exp, log, pow(<gamma>), s-curve, steps(<count>) or a list of points")]
+    InvalidCurve(String),
+    #[error("curve points need at least 2 points with increasing x")]
+    InvalidPoints,
+    #[error("unknown shared events '{0}'")]
+    UnknownSharedEvents(String),
+    #[error("var action is missing an operation, one of \"set\", \"inc\", \"dec\", \"clamp\" or \"reset\" has to be specified")]
+    VarActionMissing,
+    #[error("\"clamp\" requires \"min\" or \"max\"")]
+    ClampMissingBounds,
+    #[error("unknown var '{0}'")]
+    UnknownVar(String),
+    #[error("invalid condition '{0}'")]
+    InvalidCondition(String),
+    #[error("timer interval has to be greater than 0")]
+    TimerInterval,
+    #[error("idle \"after\" has to be greater than 0")]
+    IdleAfter,
+    #[error("queue length has to be greater than 0")]
+    QueueLength,
+    #[error("sensing timeout has to be greater than 0")]
+    SensingTimeout,
+    #[error("run timeout has to be greater than 0")]
+    RunTimeout,
+    #[error("invalid nice value {0}, expected -20 to 19")]
+    InvalidNice(i32),
+    #[error("a negative \"nice\" can't be combined with \"uid\"")]
+    NegativeNiceUid,
+    #[error("unknown pipe '{0}'")]
+    UnknownPipe(String),
+    #[error("invalid script: {0}")]
+    InvalidScript(String),
+    #[error("\"script\" requires rmidimap to be built with the \"script\" feature")]
+    ScriptDisabled,
 }
 
 impl From<alsa::Error> for Error {
@@ -17,15 +17,29 @@ lazy_static! {
         rawvalue: "rawvalue",
         timestamp: "timestamp",
         value: "value",
+        state: "state",
+        delta: "delta",
+        tick: "tick",
     };
 }
 
+// size of a dense [type][channel][id] table of events
+pub const EVENT_TABLE_SIZE: usize = 16*16*128;
+
 pub fn event_to_key(r#type: EventType, channel: u8, id: u8) -> u32 {
     (r#type as u32)*256*256 + (channel as u32)*256 + (id as u32)
 }
 
+// index in a dense table, None if channel or id are out of MIDI range
+pub fn event_to_index(r#type: EventType, channel: u8, id: u8) -> Option<usize> {
+    if channel >= 16 || id >= 128 {
+        return None;
+    }
+    Some((r#type as usize) << 11 | (channel as usize) << 7 | id as usize)
+}
+
 #[repr(u8)]
-#[derive(Serialize,Deserialize,Debug,Copy,Clone,Default,Display)]
+#[derive(Serialize,Deserialize,Debug,Copy,Clone,Default,Display,PartialEq,Eq)]
 pub enum EventType {
     #[default]
     Unknown                 = 0b0000,
@@ -103,6 +117,9 @@ pub struct EventEnv {
     pub rawvalue: String,
     pub timestamp: String,
     pub value: String,
+    pub state: String,
+    pub delta: String,
+    pub tick: String,
 }
 
 #[derive(Clone,Debug)]
@@ -113,6 +130,9 @@ struct EventEnvRef<'a> {
     pub rawvalue: &'a str,
     pub timestamp: &'a str,
     pub value: &'a str,
+    pub state: &'a str,
+    pub delta: &'a str,
+    pub tick: &'a str,
 }
 
 
@@ -130,7 +150,7 @@ impl std::fmt::Display for EventBuf {
 }
 
 impl EventBuf {
-    pub fn as_event(&self) -> Event {
+    pub fn as_event(&self) -> Event<'_> {
         Event {
             r#type: self.r#type,
             channel: self.channel,
@@ -142,15 +162,15 @@ impl EventBuf {
     }
 }
 
-impl Into<EventBuf> for Event<'_> {
-    fn into(self) -> EventBuf {
+impl From<Event<'_>> for EventBuf {
+    fn from(v: Event<'_>) -> Self {
         EventBuf {
-            r#type: self.r#type,
-            channel: self.channel,
-            id: self.id,
-            value: self.value,
-            raw: Vec::from(self.raw),
-            timestamp: self.timestamp,
+            r#type: v.r#type,
+            channel: v.channel,
+            id: v.id,
+            value: v.value,
+            raw: Vec::from(v.raw),
+            timestamp: v.timestamp,
         }
     }
 }
@@ -163,7 +183,7 @@ impl From<u8> for EventType {
         }
         else {
             // safe since all valid cases are defined
-            unsafe { std::mem::transmute(v) }
+            unsafe { std::mem::transmute::<u8, EventType>(v) }
         }
     }
 }
@@ -195,6 +215,55 @@ impl<'a> Event<'a> {
         event_to_key(self.r#type, self.channel, self.id)
     }
 
+    pub fn index(&self) -> Option<usize> {
+        event_to_index(self.r#type, self.channel, self.id)
+    }
+
+    // Active Sensing keepalive, not matched against event configs
+    pub fn is_sensing(&self) -> bool {
+        self.raw.first() == Some(&0xFE)
+    }
+
+    // button press: note or controller with non-zero value
+    pub fn is_press(&self) -> bool {
+        matches!(self.r#type, EventType::NoteOn | EventType::Controller) && self.value > 0
+    }
+
+    pub fn is_release(&self) -> bool {
+        match self.r#type {
+            EventType::NoteOff => true,
+            EventType::NoteOn | EventType::Controller => self.value == 0,
+            _ => false,
+        }
+    }
+
+    pub fn is_note_press(&self) -> bool {
+        matches!(self.r#type, EventType::NoteOn) && self.value > 0
+    }
+
+    pub fn is_note_release(&self) -> bool {
+        match self.r#type {
+            EventType::NoteOff => true,
+            EventType::NoteOn => self.value == 0,
+            _ => false,
+        }
+    }
+
+    // integer value remapped to range
+    pub fn remapped_value(&self, remap: Option<&Remapper<f64>>) -> i64 {
+        match remap {
+            Some(r) => r.remap_to::<i64>(self.value as f64).unwrap_or(r.dst().start() as i64),
+            None => self.value as i64,
+        }
+    }
+
+    pub fn remapped(&self, remap: Option<&Remapper<f64>>, float: bool) -> String {
+        match (remap,float) {
+            (Some(r),true)  => r.remap(self.value as f64).to_string(),
+            _ => self.remapped_value(remap).to_string(),
+        }
+    }
+
     pub fn make_env(&self, remap: Option<&Remapper<f64>>, float: bool) -> Result<EventEnv, Error>
     {
         Ok(EventEnv {
@@ -203,11 +272,10 @@ impl<'a> Event<'a> {
             rawvalue: self.value.to_string(),
             raw: bytes_to_strhex(self.raw, " "),
             timestamp: self.timestamp.unwrap_or(SystemTime::now()).duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64().to_string(),
-            value: match (remap,float) {
-                (Some(r),true)  => r.remap(self.value as f64).to_string(),
-                (Some(r),false) => r.remap_to::<i64>(self.value as f64).unwrap().to_string(),
-                _ => self.value.to_string(),
-            }
+            value: self.remapped(remap, float),
+            state: String::new(),
+            delta: String::new(),
+            tick: String::new(),
         })
     }
 }
@@ -256,6 +324,9 @@ impl EventEnv {
                     rawvalue: v.rawvalue.as_ref().map(|x| &x[..]).unwrap_or(EVENT_ENV_DEFAULT.rawvalue),
                     timestamp: v.timestamp.as_ref().map(|x| &x[..]).unwrap_or(EVENT_ENV_DEFAULT.timestamp),
                     value: v.value.as_ref().map(|x| &x[..]).unwrap_or(EVENT_ENV_DEFAULT.value),
+                    state: v.state.as_ref().map(|x| &x[..]).unwrap_or(EVENT_ENV_DEFAULT.state),
+                    delta: v.delta.as_ref().map(|x| &x[..]).unwrap_or(EVENT_ENV_DEFAULT.delta),
+                    tick: v.tick.as_ref().map(|x| &x[..]).unwrap_or(EVENT_ENV_DEFAULT.tick),
                 }
             }
             _ => EVENT_ENV_DEFAULT.clone(),
@@ -266,6 +337,12 @@ impl EventEnv {
         r.insert(keys.rawvalue, self.rawvalue);
         r.insert(keys.timestamp, self.timestamp);
         r.insert(keys.value, self.value);
+        // values only some jobs have are left out when unset
+        for (k, v) in [(keys.state, self.state), (keys.delta, self.delta), (keys.tick, self.tick)] {
+            if !v.is_empty() {
+                r.insert(k, v);
+            }
+        }
         r
     }
 }
@@ -1,64 +1,517 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap,HashMap};
+use std::time::SystemTime;
+use std::sync::Arc;
 
-use crate::config::{EventConfig,DeviceConfig};
-use crate::event::{EventType,Event};
-use crate::Error;
+use crate::config::{EventConfig,DeviceConfig,RunConfig};
+use crate::config::device::{Identifier,MatchMode,TimerConfig,IdleConfig,SensingConfig};
+use crate::config::event::PageMode;
+use crate::event::{Event,EventBuf,event_to_index,EVENT_TABLE_SIZE};
+use crate::gesture::{GestureGroup,button_index};
+use crate::job::{Job,Fired};
+use crate::queue::QueueConfig;
+use crate::state::DeviceState;
 
-#[derive(Debug,Default)]
-pub struct EventMap<'a> {
-    pub map: HashMap<u32, Vec<&'a EventConfig>>,
+// Indexes into the slots and gestures of a layer, 0 for none
+#[derive(Debug,Clone,Copy,Default)]
+struct Entry {
+    slot: u32,
+    gesture: u32,
+}
+
+// Event configs of the base layer or of one page
+#[derive(Debug)]
+struct Layer<'a> {
+    // dense [type][channel][id] table of indexes into slots and gestures
+    table: Box<[Entry]>,
+    // distinct lists of event configs, shared by all keys with the same configs.
+    // slot 0 is always empty
+    slots: Vec<Vec<&'a EventConfig>>,
+    // chord event configs, evaluated on every note event
+    chords: Vec<&'a EventConfig>,
+    // sequence event configs, evaluated on every event
+    sequences: Vec<&'a EventConfig>,
+    // gesture event configs of each button, gesture 0 is always empty
+    gestures: Vec<GestureGroup<'a>>,
+}
+
+impl<'a> Layer<'a> {
+    fn new() -> Self {
+        Layer {
+            table: vec![Entry::default(); EVENT_TABLE_SIZE].into_boxed_slice(),
+            slots: vec![Vec::new()],
+            chords: Vec::new(),
+            sequences: Vec::new(),
+            gestures: vec![GestureGroup::default()],
+        }
+    }
+
+    fn add_events(&mut self, events: &'a [EventConfig]) {
+        // slot resulting from appending an event config to another slot
+        let mut links: HashMap<(u32, *const EventConfig), u32> = HashMap::new();
+        for event in events {
+            if event.chord.is_some() {
+                self.chords.push(event);
+                continue;
+            }
+            if event.sequence.is_some() {
+                self.sequences.push(event);
+                continue;
+            }
+            for channel in &event.channel {
+                for id in &event.id {
+                    let index = match event_to_index(event.r#type, channel, id) {
+                        Some(v) => v,
+                        None => continue,
+                    };
+                    if event.gesture.is_some() {
+                        if self.table[index].gesture == 0 {
+                            self.gestures.push(GestureGroup::default());
+                            self.table[index].gesture = (self.gestures.len()-1) as u32;
+                        }
+                        self.gestures[self.table[index].gesture as usize].push(event);
+                        continue;
+                    }
+                    let slot = self.table[index].slot;
+                    self.table[index].slot = *links.entry((slot, event)).or_insert_with(|| {
+                        let mut v = self.slots[slot as usize].clone();
+                        v.push(event);
+                        self.slots.push(v);
+                        (self.slots.len()-1) as u32
+                    });
+                }
+            }
+        }
+    }
+
+    fn get(&self, event: &Event) -> &[&'a EventConfig] {
+        match event.index() {
+            Some(i) => &self.slots[self.table[i].slot as usize],
+            None => &[],
+        }
+    }
+
+    // gesture event configs of the button of index
+    fn gestures(&self, index: usize) -> Option<&GestureGroup<'a>> {
+        match self.table[index].gesture {
+            0 => None,
+            g => Some(&self.gestures[g as usize]),
+        }
+    }
+
+    // event configs triggered by event.
+    // Returns whether any event config is concerned by event
+    fn match_event(&self, layer: usize, event: &EventBuf, state: &mut DeviceState, fired: &mut Vec<Fired<'a>>) -> bool {
+        let ev = event.as_event();
+        let mut matched = false;
+        let start = fired.len();
+        for x in self.get(&ev).iter().filter(|x| x.match_value(&ev)) {
+            if !x.match_when(&ev, state) {
+                continue;
+            }
+            matched = true;
+            if x.match_trigger(&ev, state) {
+                fired.push((event.clone(), x));
+            }
+        }
+        if ev.is_note_press() || ev.is_note_release() {
+            for x in &self.chords {
+                matched |= x.channel.contains(&ev.channel) && x.chord.as_ref().is_some_and(|c| c.notes.contains(&ev.id)) && x.match_when(&ev, state);
+                match x.match_chord(&ev, state) {
+                    Some(run) if ev.is_note_press() || !run.is_empty() => fired.push((event.clone(), x)),
+                    _ => (),
+                }
+            }
+        }
+        for x in &self.sequences {
+            matched |= x.sequence.as_ref().is_some_and(|s| s.steps.iter().any(|x| x.matches(&ev))) && x.match_when(&ev, state);
+            if x.match_sequence(&ev, state) {
+                fired.push((event.clone(), x));
+            }
+        }
+        if let Some((index, g)) = button_index(&ev).and_then(|i| Some((i, self.gestures(i)?))) {
+            matched = true;
+            let t = ev.timestamp.unwrap_or_else(SystemTime::now);
+            if ev.is_press() {
+                g.press(state.button((layer, index)), event, t, fired);
+            }
+            else if ev.is_release() {
+                g.release(state.button((layer, index)), t, fired);
+            }
+        }
+        // event configs of a layer come from one slice, their addresses follow config order
+        fired[start..].sort_by_key(|(_, c)| *c as *const EventConfig as usize);
+        matched
+    }
 }
 
-fn event_to_key(r#type: EventType, channel: u8, id: u8) -> u32 {
-    (r#type as u32)*256*256 + (channel as u32)*256 + (id as u32)
+#[derive(Debug)]
+pub struct EventMap<'a> {
+    // base layer followed by one layer per page
+    layers: Vec<Layer<'a>>,
+    // page names, in the order of their layers
+    pages: Vec<&'a str>,
+    default_page: Option<usize>,
+    // settings of executors, the first one is the device executor
+    executors: Vec<QueueConfig>,
+    // executor of event configs not run by the device executor, keyed by config address
+    executor_of: HashMap<usize, usize>,
+    // only run the first matching event config
+    match_first: bool,
+    // commands run on events not matched by any event config
+    unmatched: &'a [RunConfig],
+    // device variables and their initial values
+    vars: Option<&'a BTreeMap<String, i64>>,
+    timers: &'a [TimerConfig],
+    idle: Option<&'a IdleConfig>,
+    sensing: Option<&'a SensingConfig>,
+    // commands of the device, run on Active Sensing loss and recovery
+    connect: &'a [RunConfig],
+    disconnect: &'a [RunConfig],
+    // name, regex or address of the device config, passed to scripts
+    #[cfg_attr(not(feature = "script"), allow(dead_code))]
+    device: &'a str,
 }
 
-pub fn count_events(events: &[EventConfig]) -> usize {
-    events.iter().map(|x| {
-        let nchannel = x.channel.len();
-        let nid = x.id.len();
-        nchannel * nid
-    }).sum()
+impl<'a> Default for EventMap<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<'a> EventMap<'a> {
+    pub fn new() -> Self {
+        EventMap {
+            layers: vec![Layer::new()],
+            pages: Vec::new(),
+            default_page: None,
+            executors: vec![QueueConfig::default()],
+            executor_of: HashMap::new(),
+            match_first: false,
+            unmatched: &[],
+            vars: None,
+            timers: &[],
+            idle: None,
+            sensing: None,
+            connect: &[],
+            disconnect: &[],
+            device: "",
+        }
+    }
+
+    // add event configs to the base layer
     pub fn add_events(&mut self, events: &'a [EventConfig]) {
-        for event in events {
-            for &channel in &event.channel {
-                for &id in &event.id {
-                    let key = event_to_key(event.r#type, channel, id);
-                    if let Some(v) = self.map.get_mut(&key) {
-                        v.push(event);
-                    }
-                    else {
-                        self.map.insert(key, Vec::from([event]));
-                    }
+        self.layers[0].add_events(events);
+    }
+
+    pub fn add_page(&mut self, name: &'a str, events: &'a [EventConfig]) {
+        let mut layer = Layer::new();
+        layer.add_events(events);
+        self.layers.push(layer);
+        self.pages.push(name);
+    }
+
+    // page active on connection
+    pub fn default_page(&self) -> Option<usize> {
+        self.default_page
+    }
+
+    // variables of the device state on connection
+    pub fn vars(&self) -> Option<&'a BTreeMap<String, i64>> {
+        self.vars
+    }
+
+    // start timers and idle tracking of the device state on connection at time t,
+    // first ticks are one interval after t
+    pub fn start(&self, t: SystemTime, state: &mut DeviceState) {
+        self.start_timers(t, state);
+        state.set_last_event(t);
+    }
+
+    fn start_timers(&self, t: SystemTime, state: &mut DeviceState) {
+        state.set_timers(self.timers.iter().map(|x| (t + x.interval, 0)).collect());
+    }
+
+    pub fn executors(&self) -> &[QueueConfig] {
+        &self.executors
+    }
+
+    // index of the executor running job
+    pub fn executor(&self, job: &Job) -> usize {
+        job.config
+            .and_then(|x| self.executor_of.get(&(x as *const EventConfig as usize)))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    // give event configs with queue settings or a group their own executors
+    fn add_executors(&mut self, events: impl Iterator<Item = &'a EventConfig>) {
+        let mut groups: HashMap<&str, usize> = HashMap::new();
+        for event in events.filter(|x| QueueConfig::is_set(x)) {
+            let group = event.group.as_deref();
+            let index = match group.and_then(|g| groups.get(g)) {
+                Some(&i) => i,
+                None => {
+                    self.executors.push(self.executors[0]);
+                    self.executors.len() - 1
                 }
+            };
+            if let Some(g) = group {
+                groups.insert(g, index);
             }
+            self.executors[index].merge(event);
+            self.executor_of.insert(event as *const EventConfig as usize, index);
         }
     }
 
-    pub fn run_event(&self, event: &Event) -> Result<(), Error > {
-        let key = event_to_key(event.r#type, event.channel, event.id);
-        if let Some(v) = self.map.get(&key) {
-            for ev in v {
-                if ev.match_value(event) {
-                    for r in &ev.run {
-                        let env = event.make_env(ev.remap.as_ref(), ev.float )?.to_map(r.envconf.as_ref());
-                        r.run(env)?;
+    // event configs of the base layer for event
+    pub fn get(&self, event: &Event) -> &[&'a EventConfig] {
+        self.layers[0].get(event)
+    }
+
+    // jobs triggered by event, updates device state
+    pub fn match_event(&self, event: &EventBuf, state: &mut DeviceState) -> Vec<Job<'a>> {
+        let ev = event.as_event();
+        if ev.is_sensing() {
+            return self.sense(ev.timestamp.unwrap_or_else(SystemTime::now), state);
+        }
+        state.update(&ev);
+        let mut fired = Vec::new();
+        let mut jobs = Vec::new();
+        let mut matched = false;
+        if let Some(idle) = self.idle {
+            if state.idle() {
+                state.set_idle(false);
+                if !idle.resume.is_empty() {
+                    jobs.push(Job::unmatched(event.clone(), &idle.resume));
+                }
+            }
+            state.set_last_event(ev.timestamp.unwrap_or_else(SystemTime::now));
+        }
+        // active page comes before the base layer
+        if let Some(p) = state.page() {
+            matched |= self.layers[p+1].match_event(p+1, event, state, &mut fired);
+        }
+        matched |= self.layers[0].match_event(0, event, state, &mut fired);
+        if !matched && !self.unmatched.is_empty() {
+            jobs.push(Job::unmatched(event.clone(), self.unmatched));
+        }
+        // release of the button holding a momentary page
+        if let (Some((button, restore)), Some(index)) = (state.page_hold(), button_index(&ev)) {
+            if ev.is_release() && button == index {
+                state.set_page_hold(None);
+                state.set_page(restore);
+            }
+        }
+        self.select(&mut fired, state);
+        for (e, c) in fired {
+            self.activate(e, c, state, &mut jobs);
+        }
+        set_vars(&mut jobs, state);
+        jobs
+    }
+
+    // order fired event configs meeting their conditions by priority, and stop after the first final one
+    fn select(&self, fired: &mut Vec<Fired<'a>>, state: &DeviceState) {
+        fired.retain(|(e, c)| c.match_when(&e.as_event(), state));
+        fired.sort_by_key(|(_, c)| std::cmp::Reverse(c.priority));
+        if let Some(i) = fired.iter().position(|(_, c)| c.r#final || self.match_first) {
+            fired.truncate(i+1);
+        }
+    }
+
+    // jobs of timed triggers and timers due at time t
+    pub fn tick(&self, t: SystemTime, state: &mut DeviceState) -> Vec<Job<'a>> {
+        // gestures of each button are selected on their own, like the configs of one event
+        let mut buttons: Vec<((usize, usize), Vec<Fired<'a>>)> = Vec::new();
+        for (key, b) in state.buttons_mut() {
+            if let Some(g) = self.layers[key.0].gestures(key.1) {
+                let mut fired = Vec::new();
+                g.tick(b, t, &mut fired);
+                if !fired.is_empty() {
+                    buttons.push((*key, fired));
+                }
+            }
+        }
+        buttons.sort_by_key(|x| x.0);
+        let mut jobs = Vec::new();
+        for (_, mut fired) in buttons {
+            self.select(&mut fired, state);
+            for (e, c) in fired {
+                self.activate(e, c, state, &mut jobs);
+            }
+        }
+        for (timer, (next, tick)) in self.timers.iter().zip(state.timers_mut()) {
+            if *next <= t {
+                // late ticks are skipped rather than run in a burst
+                while *next <= t {
+                    *next += timer.interval;
+                }
+                *tick += 1;
+                jobs.push(Job::timed(&timer.run, t).with_tick(*tick));
+            }
+        }
+        if let (Some(sensing), Some(last)) = (self.sensing, state.sensing()) {
+            if !state.sensing_lost() && last + sensing.timeout <= t {
+                state.set_sensing_lost(true);
+                if !sensing.lost.is_empty() {
+                    jobs.push(Job::timed(&sensing.lost, t));
+                }
+                if sensing.disconnect {
+                    state.set_timers(Vec::new());
+                    if !self.disconnect.is_empty() {
+                        jobs.push(Job::timed(self.disconnect, t));
                     }
                 }
             }
         }
-        Ok(())
+        if let (Some(idle), Some(last)) = (self.idle, state.last_event()) {
+            if !state.idle() && last + idle.after <= t {
+                state.set_idle(true);
+                if !idle.run.is_empty() {
+                    jobs.push(Job::timed(&idle.run, t));
+                }
+            }
+        }
+        set_vars(&mut jobs, state);
+        jobs
+    }
+
+    // Active Sensing received at time t, recovers a lost link
+    fn sense(&self, t: SystemTime, state: &mut DeviceState) -> Vec<Job<'a>> {
+        state.set_sensing(t);
+        let mut jobs = Vec::new();
+        if let Some(sensing) = self.sensing.filter(|_| state.sensing_lost()) {
+            state.set_sensing_lost(false);
+            if sensing.disconnect {
+                if !self.connect.is_empty() {
+                    jobs.push(Job::timed(self.connect, t));
+                }
+                self.start_timers(t, state);
+            }
+            if !sensing.resumed.is_empty() {
+                jobs.push(Job::timed(&sensing.resumed, t));
+            }
+            set_vars(&mut jobs, state);
+        }
+        jobs
+    }
+
+    // Active Sensing loss already ran the disconnect commands of the device
+    pub fn disconnected(&self, state: &DeviceState) -> bool {
+        self.sensing.is_some_and(|x| x.disconnect) && state.sensing_lost()
+    }
+
+    // next time a timed trigger or a timer can be due
+    pub fn deadline(&self, state: &DeviceState) -> Option<SystemTime> {
+        state.buttons()
+            .filter_map(|((layer, index), b)| self.layers[*layer].gestures(*index)?.deadline(b))
+            .chain(state.timers().iter().map(|x| x.0))
+            .chain(self.idle.filter(|_| !state.idle()).and_then(|x| Some(state.last_event()? + x.after)))
+            .chain(self.sensing.filter(|_| !state.sensing_lost()).and_then(|x| Some(state.sensing()? + x.timeout)))
+            .min()
+    }
+
+    // apply the actions of a triggered event config
+    fn activate(&self, event: EventBuf, config: &'a EventConfig, state: &mut DeviceState, jobs: &mut Vec<Job<'a>>) {
+        let ev = event.as_event();
+        // chord releases only run their release commands
+        if let Some(chord) = config.chord.as_ref().filter(|_| ev.is_note_release()) {
+            jobs.push(Job::new(event, config, &chord.release));
+            return;
+        }
+        let step = match &config.cycle {
+            // button releases don't advance toggles and cycles
+            Some(_) if button_index(&ev).is_some() && !ev.is_press() => return,
+            Some(c) => Some(&c.steps[state.next_cycle(config as *const EventConfig as usize, c)]),
+            None => None,
+        };
+        if let Some(name) = &config.page {
+            self.switch_page(name, config.page_mode, &ev, state);
+        }
+        let relative = config.relative.map(|r| {
+            let delta = r.decode(ev.value);
+            let value = match (&config.accumulate, ev.index()) {
+                (Some(range), Some(i)) => state.accumulate((config as *const EventConfig as usize, i), delta, range),
+                _ => delta,
+            };
+            (delta, value)
+        });
+        if !config.vars.is_empty() {
+            let value = match relative {
+                Some((_, v)) => v,
+                None => ev.remapped_value(config.remap.as_ref()),
+            };
+            for a in &config.vars {
+                state.apply_var(a, value);
+            }
+        }
+        // a script returning false cancels the commands of the event
+        #[cfg(feature = "script")]
+        if let Some(script) = &config.script {
+            if !script.run(&ev, config, step.map(|x| x.0.as_str()), relative, self.device, state) {
+                return;
+            }
+        }
+        let job = match step {
+            Some((name, run)) => Job::new(event, config, run).with_state(name),
+            None => Job::new(event, config, &config.run),
+        };
+        if job.run.is_empty() && config.pipe.is_empty() {
+            return;
+        }
+        jobs.push(match relative {
+            Some((delta, value)) => job.with_relative(delta, value),
+            None => job,
+        });
+    }
+
+    fn switch_page(&self, name: &str, mode: PageMode, event: &Event, state: &mut DeviceState) {
+        let page = match name {
+            "base" => None,
+            _ => match self.pages.iter().position(|x| *x == name) {
+                Some(i) => Some(i),
+                None => return,
+            },
+        };
+        match mode {
+            PageMode::Switch => state.set_page(page),
+            PageMode::Toggle if state.page() == page => state.set_page(state.previous_page()),
+            PageMode::Toggle => state.set_page(page),
+            PageMode::Hold => {
+                let index = button_index(event);
+                // the release already restored the page in match_event
+                if index.is_some() && !event.is_press() {
+                    return;
+                }
+                if let Some(index) = index {
+                    // nested holds restore the page from before the first one
+                    let restore = state.page_hold().map_or(state.page(), |(_, p)| p);
+                    state.set_page_hold(Some((index, restore)));
+                }
+                state.set_page(page);
+            }
+        }
+    }
+}
+
+// give jobs exporting env the variables after all actions of their event, shared by all of them
+fn set_vars(jobs: &mut [Job], state: &DeviceState) {
+    if !jobs.iter().any(Job::exports_env) {
+        return;
+    }
+    let vars = state.vars();
+    if vars.is_empty() {
+        return;
+    }
+    let vars: Arc<[(String, i64)]> = vars.into_iter().map(|(k, v)| (format!("var_{}", k), v)).collect();
+    for j in jobs.iter_mut().filter(|x| x.exports_env()) {
+        j.vars = vars.clone();
     }
 }
 
 impl<'a> From<&'a [EventConfig]> for EventMap<'a> {
     fn from(events: &'a [EventConfig]) -> Self {
-        // init hashmap with size for optimizing
-        let size = count_events(events);
-        let mut ret = EventMap { map: HashMap::with_capacity(size) };
+        let mut ret = EventMap::new();
         // insert references
         ret.add_events(events);
         ret
@@ -68,15 +521,299 @@ impl<'a> From<&'a [EventConfig]> for EventMap<'a> {
 
 impl<'a> From<&'a DeviceConfig> for EventMap<'a> {
     fn from(device: &'a DeviceConfig) -> Self {
-        // init hashmap with size for optimizing
-        let size = count_events(device.events.as_ref().map(|x| &x[..]).unwrap_or(&[]));
-        //let size = events.iter().map(|x| x.channels.len()*x.ids.len() ).sum();
-        let mut ret = EventMap { map: HashMap::with_capacity(size) };
+        let mut ret = EventMap::new();
         // insert references
         if let Some(x) = device.events.as_ref() {
             ret.add_events(x);
         }
+        for page in &device.pages {
+            ret.add_page(&page.name, &page.events);
+        }
+        ret.default_page = device.default_page.as_ref().and_then(|x| device.page_index(x).ok().flatten());
+        ret.executors[0] = QueueConfig::from(device);
+        ret.match_first = device.r#match == MatchMode::First;
+        ret.unmatched = &device.unmatched;
+        ret.vars = Some(&device.vars);
+        ret.timers = &device.timers;
+        ret.idle = device.idle.as_ref();
+        ret.sensing = Some(&device.sensing);
+        ret.connect = device.connect.as_deref().unwrap_or_default();
+        ret.disconnect = device.disconnect.as_deref().unwrap_or_default();
+        ret.device = match &device.identifier {
+            Identifier::Name(x) | Identifier::Addr(x) => x,
+            Identifier::Regex(x) => x.as_str(),
+            Identifier::All => "",
+        };
+        let pages = device.pages.iter().flat_map(|x| x.events.iter());
+        ret.add_executors(device.events.iter().flatten().chain(pages));
         ret
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::config::Config;
+    use crate::event::EventType;
+
+    // device config from the yaml of its fields
+    fn device(yaml: &str) -> DeviceConfig {
+        let indent = yaml.lines().filter(|l| !l.trim().is_empty()).map(|l| l.len() - l.trim_start().len()).min().unwrap_or(0);
+        let fields: String = yaml.lines().filter(|l| !l.trim().is_empty()).map(|l| format!("    {}\n", &l[indent..])).collect();
+        let config: Config = format!("devices:\n  - name: test\n{}", fields).parse().unwrap();
+        config.devices.into_iter().next().unwrap()
+    }
+
+    fn state(map: &EventMap) -> DeviceState {
+        let mut state = DeviceState::new();
+        state.set_page(map.default_page());
+        if let Some(vars) = map.vars() {
+            state.set_vars(vars);
+        }
+        map.start(at(0), &mut state);
+        state
+    }
+
+    fn at(ms: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_millis(ms)
+    }
+
+    fn ev(r#type: EventType, id: u8, value: u16, ms: u64) -> EventBuf {
+        EventBuf { r#type, channel: 0, id, value, raw: vec![], timestamp: Some(at(ms)) }
+    }
+
+    fn note_on(id: u8, ms: u64) -> EventBuf {
+        ev(EventType::NoteOn, id, 100, ms)
+    }
+
+    fn note_off(id: u8, ms: u64) -> EventBuf {
+        ev(EventType::NoteOn, id, 0, ms)
+    }
+
+    // commands of jobs, "cmd: name" gives name
+    fn cmds(jobs: &[Job]) -> Vec<String> {
+        jobs.iter().flat_map(|j| j.run.iter().map(|r| r.args.last().unwrap().clone())).collect()
+    }
+
+    #[test]
+    fn page_hold() {
+        let d = device(r#"
+            events:
+              - { type: Note, id: 21, page: shift, page_mode: hold }
+              - { type: NoteOn, id: 60, value: 1-127, run: [ cmd: base ] }
+            pages:
+              shift:
+                events:
+                  - { type: NoteOn, id: 60, value: 1-127, run: [ cmd: shift ], final: true }
+        "#);
+        let map = EventMap::from(&d);
+        let mut s = state(&map);
+        map.match_event(&note_on(21, 0), &mut s);
+        assert_eq!(cmds(&map.match_event(&note_on(60, 10), &mut s)), ["shift"]);
+        // release as NoteOn with velocity 0, matched by the hold config
+        map.match_event(&note_off(21, 20), &mut s);
+        assert_eq!(s.page(), None);
+        assert_eq!(cmds(&map.match_event(&note_on(60, 30), &mut s)), ["base"]);
+        // release as NoteOff
+        map.match_event(&note_on(21, 40), &mut s);
+        assert_eq!(s.page(), Some(0));
+        map.match_event(&ev(EventType::NoteOff, 21, 0, 50), &mut s);
+        assert_eq!(s.page(), None);
+    }
+
+    #[test]
+    fn page_change_ends_gestures() {
+        let d = device(r#"
+            events:
+              - { type: NoteOn, id: 1, page: shift }
+              - { type: NoteOn, id: 2, page: base }
+            pages:
+              shift:
+                events:
+                  - { type: NoteOn, id: 60, gesture: hold_repeat(100ms), run: [ cmd: repeat ] }
+        "#);
+        let map = EventMap::from(&d);
+        let mut s = state(&map);
+        map.match_event(&note_on(1, 0), &mut s);
+        assert_eq!(cmds(&map.match_event(&note_on(60, 10), &mut s)), ["repeat"]);
+        assert_eq!(cmds(&map.tick(at(115), &mut s)), ["repeat"]);
+        map.match_event(&note_on(2, 150), &mut s);
+        assert!(map.match_event(&note_off(60, 160), &mut s).is_empty());
+        assert!(map.tick(at(1000), &mut s).is_empty());
+        assert_eq!(map.deadline(&s), None);
+    }
+
+    #[test]
+    fn when_unmatched() {
+        let d = device(r#"
+            vars: { mode: 0 }
+            unmatched: [ cmd: unmatched ]
+            events:
+              - { type: NoteOn, id: 60, when: { var: mode == 1 }, run: [ cmd: mode ] }
+        "#);
+        let map = EventMap::from(&d);
+        let mut s = state(&map);
+        assert_eq!(cmds(&map.match_event(&note_on(60, 0), &mut s)), ["unmatched"]);
+        s.set_var("mode", 1);
+        assert_eq!(cmds(&map.match_event(&note_on(60, 10), &mut s)), ["mode"]);
+    }
+
+    #[test]
+    fn config_order() {
+        let d = device(r#"
+            events:
+              - { type: NoteOn, id: 60, gesture: hold_repeat, run: [ cmd: gesture ] }
+              - { chord: [ 60 ], run: [ cmd: chord ] }
+              - { type: NoteOn, id: 60, run: [ cmd: plain ] }
+              - { sequence: [ { type: NoteOn, id: 60 } ], run: [ cmd: sequence ] }
+        "#);
+        let map = EventMap::from(&d);
+        let mut s = state(&map);
+        assert_eq!(cmds(&map.match_event(&note_on(60, 0), &mut s)), ["gesture", "chord", "plain", "sequence"]);
+    }
+
+    #[test]
+    fn chords() {
+        let d = device(r#"
+            events:
+              - { chord: [ C4, E4, G4 ], run: [ cmd: exact ], release: [ cmd: released ] }
+              - { chord: [ C4, E4 ], chord_match: superset, tolerance: 100ms, run: [ cmd: superset ] }
+        "#);
+        let map = EventMap::from(&d);
+        let mut s = state(&map);
+        assert!(map.match_event(&note_on(60, 0), &mut s).is_empty());
+        assert_eq!(cmds(&map.match_event(&note_on(64, 50), &mut s)), ["superset"]);
+        assert_eq!(cmds(&map.match_event(&note_on(67, 80), &mut s)), ["exact"]);
+        // a chord fires once while held, and its release once
+        assert!(map.match_event(&note_on(67, 90), &mut s).is_empty());
+        assert_eq!(cmds(&map.match_event(&note_off(64, 100), &mut s)), ["released"]);
+        assert!(map.match_event(&note_off(60, 110), &mut s).is_empty());
+        assert!(map.match_event(&note_off(67, 120), &mut s).is_empty());
+        // other held notes prevent exact matches only
+        map.match_event(&note_on(59, 200), &mut s);
+        map.match_event(&note_on(60, 210), &mut s);
+        assert_eq!(cmds(&map.match_event(&note_on(64, 220), &mut s)), ["superset"]);
+        assert!(map.match_event(&note_on(67, 230), &mut s).is_empty());
+        for id in [59, 60, 64, 67] {
+            map.match_event(&note_off(id, 300), &mut s);
+        }
+        // presses further apart than tolerance
+        map.match_event(&note_on(60, 400), &mut s);
+        assert!(map.match_event(&note_on(64, 600), &mut s).is_empty());
+    }
+
+    #[test]
+    fn sequences() {
+        let d = device(r#"
+            events:
+              - sequence: [ { type: NoteOn, id: 1 }, { type: NoteOn, id: 3 }, { type: NoteOn, id: 1 } ]
+                timeout: 1s
+                run: [ cmd: combo ]
+        "#);
+        let map = EventMap::from(&d);
+        let mut s = state(&map);
+        let mut play = |notes: &[(u8, u64)]| -> Vec<String> {
+            notes.iter().flat_map(|&(id, t)| {
+                // releases are not steps
+                let mut r = cmds(&map.match_event(&note_on(id, t), &mut s));
+                r.extend(cmds(&map.match_event(&note_off(id, t+1), &mut s)));
+                r
+            }).collect()
+        };
+        assert_eq!(play(&[(1, 0), (3, 100), (1, 200)]), ["combo"]);
+        // a wrong step resets progress, a first step starts again
+        assert!(play(&[(1, 300), (2, 400), (3, 500)]).is_empty());
+        assert_eq!(play(&[(1, 600), (1, 700), (3, 800), (1, 900)]), ["combo"]);
+        // timeout from the first step
+        assert!(play(&[(1, 1000), (3, 1500), (1, 2100)]).is_empty());
+        assert_eq!(play(&[(3, 2200), (1, 2300)]), ["combo"]);
+    }
+
+    #[test]
+    fn page_switch_toggle() {
+        let d = device(r#"
+            default_page: a
+            events:
+              - { type: NoteOn, id: 1, value: 1-127, page: b }
+              - { type: NoteOn, id: 2, value: 1-127, page: a, page_mode: toggle }
+              - { type: NoteOn, id: 3, value: 1-127, page: base }
+              - { type: NoteOn, id: 60, value: 1-127, run: [ cmd: base ] }
+            pages:
+              a:
+                events:
+                  - { type: NoteOn, id: 60, value: 1-127, run: [ cmd: a ] }
+              b:
+                events:
+                  - { type: NoteOn, id: 61, value: 1-127, run: [ cmd: b ] }
+        "#);
+        let map = EventMap::from(&d);
+        let mut s = state(&map);
+        // active page before the base layer
+        assert_eq!(cmds(&map.match_event(&note_on(60, 0), &mut s)), ["a", "base"]);
+        map.match_event(&note_on(1, 10), &mut s);
+        assert_eq!(s.page(), Some(1));
+        assert_eq!(cmds(&map.match_event(&note_on(60, 20), &mut s)), ["base"]);
+        assert_eq!(cmds(&map.match_event(&note_on(61, 30), &mut s)), ["b"]);
+        // toggle goes back to the previous page
+        map.match_event(&note_on(2, 40), &mut s);
+        assert_eq!(s.page(), Some(0));
+        map.match_event(&note_on(2, 50), &mut s);
+        assert_eq!(s.page(), Some(1));
+        map.match_event(&note_on(3, 60), &mut s);
+        assert_eq!(s.page(), None);
+        assert!(map.match_event(&note_on(61, 70), &mut s).is_empty());
+    }
+
+    #[test]
+    fn priority_final() {
+        let d = device(r#"
+            events:
+              - { type: NoteOn, id: 60, run: [ cmd: low ], priority: -1 }
+              - { type: NoteOn, id: 60, run: [ cmd: first ] }
+              - { type: NoteOn, id: 60, run: [ cmd: high ], priority: 5 }
+              - { type: NoteOn, id: 60, run: [ cmd: second ] }
+              - { type: NoteOn, id: 61, run: [ cmd: final ], final: true }
+              - { type: NoteOn, id: 61, run: [ cmd: after ] }
+              - { type: NoteOn, id: 62, run: [ cmd: skipped ], final: true, when: { held: [ 0 ] } }
+              - { type: NoteOn, id: 62, run: [ cmd: kept ] }
+        "#);
+        let map = EventMap::from(&d);
+        let mut s = state(&map);
+        assert_eq!(cmds(&map.match_event(&note_on(60, 0), &mut s)), ["high", "first", "second", "low"]);
+        assert_eq!(cmds(&map.match_event(&note_on(61, 0), &mut s)), ["final"]);
+        // configs not meeting their conditions don't stop the others
+        assert_eq!(cmds(&map.match_event(&note_on(62, 0), &mut s)), ["kept"]);
+    }
+
+    #[test]
+    fn match_first() {
+        let d = device(r#"
+            match: first
+            events:
+              - { type: NoteOn, id: 60, run: [ cmd: first ] }
+              - { type: NoteOn, id: 60, run: [ cmd: high ], priority: 1 }
+              - { type: NoteOn, id: 61, run: [ cmd: other ] }
+        "#);
+        let map = EventMap::from(&d);
+        let mut s = state(&map);
+        assert_eq!(cmds(&map.match_event(&note_on(60, 0), &mut s)), ["high"]);
+        assert_eq!(cmds(&map.match_event(&note_on(61, 0), &mut s)), ["other"]);
+    }
+
+    #[test]
+    fn tick_selects_per_button() {
+        let d = device(r#"
+            match: first
+            events:
+              - { type: NoteOn, id: 60, gesture: long_press(100ms), run: [ cmd: a ] }
+              - { type: NoteOn, id: 61, gesture: long_press(100ms), run: [ cmd: b ] }
+        "#);
+        let map = EventMap::from(&d);
+        let mut s = state(&map);
+        map.match_event(&note_on(60, 0), &mut s);
+        map.match_event(&note_on(61, 0), &mut s);
+        assert_eq!(cmds(&map.tick(at(100), &mut s)), ["a", "b"]);
+    }
+}
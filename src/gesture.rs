@@ -0,0 +1,336 @@
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use crate::config::EventConfig;
+use crate::error::ConfigError;
+use crate::event::{Event, EventBuf, EventType, event_to_index};
+use crate::job::Fired;
+
+use serde::Deserialize;
+
+const DOUBLE_TAP_WINDOW: Duration = Duration::from_millis(300);
+const LONG_PRESS_DURATION: Duration = Duration::from_millis(500);
+const HOLD_REPEAT_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Deserialize,Debug,Clone,Copy,PartialEq,Eq)]
+#[serde(try_from = "String")]
+pub enum Gesture {
+    Tap,
+    // maximum time between release and second press
+    DoubleTap(Duration),
+    // minimum hold time
+    LongPress(Duration),
+    // repeat interval while held
+    HoldRepeat(Duration),
+}
+
+impl FromStr for Gesture {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ConfigError::InvalidGesture(s.to_string());
+        let s = s.trim();
+        let (name, arg) = match s.find('(') {
+            Some(i) => {
+                let arg = s[i+1..].strip_suffix(')').ok_or_else(err)?;
+                let d = duration_str::parse(arg.trim()).map_err(|_| err())?;
+                if d.is_zero() {
+                    return Err(err());
+                }
+                (s[..i].trim(), Some(d))
+            }
+            None => (s, None),
+        };
+        match name {
+            "tap" if arg.is_none() => Ok(Gesture::Tap),
+            "double_tap"  => Ok(Gesture::DoubleTap(arg.unwrap_or(DOUBLE_TAP_WINDOW))),
+            "long_press"  => Ok(Gesture::LongPress(arg.unwrap_or(LONG_PRESS_DURATION))),
+            "hold_repeat" => Ok(Gesture::HoldRepeat(arg.unwrap_or(HOLD_REPEAT_INTERVAL))),
+            _ => Err(err()),
+        }
+    }
+}
+
+impl TryFrom<String> for Gesture {
+    type Error = ConfigError;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+// index of the button an event belongs to, NoteOn and NoteOff share the same button
+pub fn button_index(event: &Event) -> Option<usize> {
+    let t = match event.r#type {
+        EventType::NoteOn | EventType::NoteOff => EventType::NoteOn,
+        EventType::Controller => EventType::Controller,
+        _ => return None,
+    };
+    event_to_index(t, event.channel, event.id)
+}
+
+// Gesture tracking of one button of a device
+#[derive(Debug,Clone)]
+pub struct ButtonState {
+    // press time and event while held
+    press: Option<(SystemTime, EventBuf)>,
+    // time up to which timed gestures have been evaluated
+    checked: SystemTime,
+    // press was used by a long press or double tap: no tap on release
+    consumed: bool,
+    // released tap waiting for a possible double tap: release time and press event
+    pending: Option<(SystemTime, EventBuf)>,
+}
+
+impl Default for ButtonState {
+    fn default() -> Self {
+        Self {
+            press: None,
+            checked: SystemTime::UNIX_EPOCH,
+            consumed: false,
+            pending: None,
+        }
+    }
+}
+
+// Gesture event configs of one button
+#[derive(Debug,Default)]
+pub struct GestureGroup<'a> {
+    configs: Vec<&'a EventConfig>,
+    // longest double tap window, taps are delayed by it
+    double_tap: Option<Duration>,
+}
+
+fn since(t: SystemTime, from: SystemTime) -> Duration {
+    t.duration_since(from).unwrap_or_default()
+}
+
+impl<'a> GestureGroup<'a> {
+    pub fn push(&mut self, config: &'a EventConfig) {
+        if let Some(Gesture::DoubleTap(w)) = config.gesture {
+            self.double_tap = self.double_tap.max(Some(w));
+        }
+        self.configs.push(config);
+    }
+
+    fn fire<F>(&self, event: &EventBuf, fired: &mut Vec<Fired<'a>>, f: F)
+    where
+        F: Fn(Gesture) -> bool,
+    {
+        for &c in &self.configs {
+            if c.gesture.map(&f).unwrap_or(false) {
+                fired.push((event.clone(), c));
+            }
+        }
+    }
+
+    pub fn press(&self, b: &mut ButtonState, event: &EventBuf, t: SystemTime, fired: &mut Vec<Fired<'a>>) {
+        let mut double = false;
+        if let Some((release, ev)) = b.pending.take() {
+            let gap = since(t, release);
+            let is_double = |g| matches!(g, Gesture::DoubleTap(w) if gap < w);
+            double = self.configs.iter().any(|c| c.gesture.map(is_double).unwrap_or(false));
+            if double {
+                self.fire(event, fired, is_double);
+            }
+            else {
+                self.fire(&ev, fired, |g| g == Gesture::Tap);
+            }
+        }
+        b.press = Some((t, event.clone()));
+        b.checked = t;
+        b.consumed = double;
+        self.fire(event, fired, |g| matches!(g, Gesture::HoldRepeat(_)));
+    }
+
+    pub fn release(&self, b: &mut ButtonState, t: SystemTime, fired: &mut Vec<Fired<'a>>) {
+        // catch up on timed gestures in case release came before the timer
+        self.tick(b, t, fired);
+        let ev = match b.press.take() {
+            Some((_, ev)) => ev,
+            None => return,
+        };
+        if b.consumed {
+            return;
+        }
+        match self.double_tap {
+            Some(_) => b.pending = Some((t, ev)),
+            None => self.fire(&ev, fired, |g| g == Gesture::Tap),
+        }
+    }
+
+    // fire gestures that are due at time t
+    pub fn tick(&self, b: &mut ButtonState, t: SystemTime, fired: &mut Vec<Fired<'a>>) {
+        if let (Some((release, ev)), Some(w)) = (&b.pending, self.double_tap) {
+            if since(t, *release) >= w {
+                self.fire(ev, fired, |g| g == Gesture::Tap);
+                b.pending = None;
+            }
+        }
+        if let Some((press, ev)) = &b.press {
+            let (from, to) = (since(b.checked, *press), since(t, *press));
+            if to <= from {
+                return;
+            }
+            for &c in &self.configs {
+                match c.gesture {
+                    Some(Gesture::LongPress(d)) if from < d && d <= to => {
+                        fired.push((ev.clone(), c));
+                        b.consumed = true;
+                    }
+                    Some(Gesture::HoldRepeat(i)) if to.as_nanos()/i.as_nanos() > from.as_nanos()/i.as_nanos() => {
+                        fired.push((ev.clone(), c));
+                    }
+                    _ => (),
+                }
+            }
+            b.checked = t;
+        }
+    }
+
+    // next time a gesture can be due
+    pub fn deadline(&self, b: &ButtonState) -> Option<SystemTime> {
+        let mut r: Option<SystemTime> = None;
+        let mut next = |t: SystemTime| r = Some(r.map_or(t, |x| x.min(t)));
+        if let (Some((release, _)), Some(w)) = (&b.pending, self.double_tap) {
+            next(*release + w);
+        }
+        if let Some((press, _)) = &b.press {
+            let from = since(b.checked, *press);
+            for c in &self.configs {
+                match c.gesture {
+                    Some(Gesture::LongPress(d)) if d > from => next(*press + d),
+                    Some(Gesture::HoldRepeat(i)) => {
+                        let n = from.as_nanos()/i.as_nanos() + 1;
+                        next(*press + Duration::from_nanos((i.as_nanos()*n) as u64));
+                    }
+                    _ => (),
+                }
+            }
+        }
+        r
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::serializer::EventConfigSerializer;
+
+    fn config(gesture: &str) -> EventConfig {
+        let yaml = format!("{{ type: NoteOn, id: 60, gesture: '{}', run: [ cmd: '{}' ] }}", gesture, gesture);
+        EventConfig::try_from(serde_yaml::from_str::<EventConfigSerializer>(&yaml).unwrap()).unwrap()
+    }
+
+    fn group(configs: &[EventConfig]) -> GestureGroup<'_> {
+        let mut g = GestureGroup::default();
+        for c in configs {
+            g.push(c);
+        }
+        g
+    }
+
+    fn at(ms: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_millis(ms)
+    }
+
+    fn ev(ms: u64) -> EventBuf {
+        EventBuf { r#type: EventType::NoteOn, channel: 0, id: 60, value: 100, raw: vec![], timestamp: Some(at(ms)) }
+    }
+
+    // gestures fired by a press, release or tick at time ms
+    fn press(g: &GestureGroup, b: &mut ButtonState, ms: u64) -> Vec<String> {
+        let mut fired = Vec::new();
+        g.press(b, &ev(ms), at(ms), &mut fired);
+        names(&fired)
+    }
+
+    fn release(g: &GestureGroup, b: &mut ButtonState, ms: u64) -> Vec<String> {
+        let mut fired = Vec::new();
+        g.release(b, at(ms), &mut fired);
+        names(&fired)
+    }
+
+    fn tick(g: &GestureGroup, b: &mut ButtonState, ms: u64) -> Vec<String> {
+        let mut fired = Vec::new();
+        g.tick(b, at(ms), &mut fired);
+        names(&fired)
+    }
+
+    fn names(fired: &[Fired]) -> Vec<String> {
+        fired.iter().map(|(_, c)| c.run[0].args.last().unwrap().clone()).collect()
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("tap".parse::<Gesture>().unwrap(), Gesture::Tap);
+        assert_eq!("double_tap".parse::<Gesture>().unwrap(), Gesture::DoubleTap(DOUBLE_TAP_WINDOW));
+        assert_eq!("long_press( 1s )".parse::<Gesture>().unwrap(), Gesture::LongPress(Duration::from_secs(1)));
+        assert_eq!("hold_repeat(50ms)".parse::<Gesture>().unwrap(), Gesture::HoldRepeat(Duration::from_millis(50)));
+        for s in ["", "tap(1s)", "hold_repeat(0ms)", "long_press(1s", "swipe"] {
+            assert!(s.parse::<Gesture>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn tap() {
+        let configs = [config("tap")];
+        let (g, mut b) = (group(&configs), ButtonState::default());
+        assert!(press(&g, &mut b, 0).is_empty());
+        assert_eq!(release(&g, &mut b, 100), ["tap"]);
+        // a release without press
+        assert!(release(&g, &mut b, 200).is_empty());
+    }
+
+    #[test]
+    fn double_tap() {
+        let configs = [config("tap"), config("double_tap(300ms)")];
+        let (g, mut b) = (group(&configs), ButtonState::default());
+        press(&g, &mut b, 0);
+        assert!(release(&g, &mut b, 50).is_empty());
+        assert_eq!(press(&g, &mut b, 200), ["double_tap(300ms)"]);
+        assert!(release(&g, &mut b, 250).is_empty());
+        // taps wait for the double tap window
+        press(&g, &mut b, 1000);
+        release(&g, &mut b, 1050);
+        assert_eq!(g.deadline(&b), Some(at(1350)));
+        assert!(tick(&g, &mut b, 1300).is_empty());
+        assert_eq!(tick(&g, &mut b, 1350), ["tap"]);
+        // a second press after the window is a new tap
+        press(&g, &mut b, 2000);
+        release(&g, &mut b, 2050);
+        assert_eq!(press(&g, &mut b, 2400), ["tap"]);
+    }
+
+    #[test]
+    fn long_press() {
+        let configs = [config("tap"), config("long_press(500ms)")];
+        let (g, mut b) = (group(&configs), ButtonState::default());
+        press(&g, &mut b, 0);
+        assert_eq!(g.deadline(&b), Some(at(500)));
+        assert!(tick(&g, &mut b, 400).is_empty());
+        assert_eq!(tick(&g, &mut b, 500), ["long_press(500ms)"]);
+        assert!(tick(&g, &mut b, 900).is_empty());
+        assert!(release(&g, &mut b, 1000).is_empty());
+        // release before the timer catches up
+        press(&g, &mut b, 2000);
+        assert_eq!(release(&g, &mut b, 2600), ["long_press(500ms)"]);
+        press(&g, &mut b, 3000);
+        assert_eq!(release(&g, &mut b, 3100), ["tap"]);
+    }
+
+    #[test]
+    fn hold_repeat() {
+        let configs = [config("hold_repeat(100ms)")];
+        let (g, mut b) = (group(&configs), ButtonState::default());
+        assert_eq!(press(&g, &mut b, 0), ["hold_repeat(100ms)"]);
+        assert_eq!(g.deadline(&b), Some(at(100)));
+        assert!(tick(&g, &mut b, 50).is_empty());
+        assert_eq!(tick(&g, &mut b, 100), ["hold_repeat(100ms)"]);
+        // late ticks don't run in a burst
+        assert_eq!(tick(&g, &mut b, 350), ["hold_repeat(100ms)"]);
+        assert_eq!(g.deadline(&b), Some(at(400)));
+        assert!(release(&g, &mut b, 380).is_empty());
+        assert_eq!(g.deadline(&b), None);
+        assert!(tick(&g, &mut b, 1000).is_empty());
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::config::{EventConfig, EventEnvMap, RunConfig};
+use crate::event::EventBuf;
+use crate::Error;
+
+// Event config triggered by an event, before its actions are resolved
+pub type Fired<'a> = (EventBuf, &'a EventConfig);
+
+// Run list triggered by an event, queued for execution
+#[derive(Debug,Clone)]
+pub struct Job<'a> {
+    pub event: EventBuf,
+    // None for the unmatched run list of a device
+    pub config: Option<&'a EventConfig>,
+    pub run: &'a [RunConfig],
+    // toggle or cycle state, exported as $state
+    pub state: Option<&'a str>,
+    // decoded delta of a relative control and its value, exported as $delta and $value
+    pub relative: Option<(i64, i64)>,
+    // variables at the time of the event, keyed by env name: var_<name>
+    pub vars: Arc<[(String, i64)]>,
+    // number of the tick of a timer, exported as $tick
+    pub tick: Option<u64>,
+}
+
+impl<'a> Job<'a> {
+    pub fn new(event: EventBuf, config: &'a EventConfig, run: &'a [RunConfig]) -> Self {
+        Self {
+            event,
+            config: Some(config),
+            run,
+            state: None,
+            relative: None,
+            vars: Arc::default(),
+            tick: None,
+        }
+    }
+
+    // run list of the device on event: unmatched events, idle resume
+    pub fn unmatched(event: EventBuf, run: &'a [RunConfig]) -> Self {
+        Self {
+            event,
+            config: None,
+            run,
+            state: None,
+            relative: None,
+            vars: Arc::default(),
+            tick: None,
+        }
+    }
+
+    // run list due at time t, without event: timers, idle
+    pub fn timed(run: &'a [RunConfig], t: SystemTime) -> Self {
+        Self {
+            event: EventBuf { timestamp: Some(t), ..Default::default() },
+            config: None,
+            run,
+            state: None,
+            relative: None,
+            vars: Arc::default(),
+            tick: None,
+        }
+    }
+
+    pub fn with_tick(mut self, tick: u64) -> Self {
+        self.tick = Some(tick);
+        self
+    }
+
+    pub fn with_relative(mut self, delta: i64, value: i64) -> Self {
+        self.relative = Some((delta, value));
+        self
+    }
+
+    pub fn with_state(mut self, state: &'a str) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    // whether commands or pipes get the env of the job
+    pub fn exports_env(&self) -> bool {
+        !self.run.is_empty() || self.config.is_some_and(|c| !c.pipe.is_empty())
+    }
+
+    // replace by a newer job of the same key, keeping the moves of relative controls it replaces
+    pub fn coalesce(&mut self, newer: Job<'a>) {
+        let old = self.relative.map(|x| x.0);
+        *self = newer;
+        if let (Some(old), Some((delta, value))) = (old, self.relative.as_mut()) {
+            *delta += old;
+            // without accumulate, value is the delta
+            if self.config.is_some_and(|c| c.accumulate.is_none()) {
+                *value = *delta;
+            }
+        }
+    }
+
+    // jobs of the same key are interchangeable, only the latest value matters
+    pub fn key(&self) -> (usize, usize, u32) {
+        (self.config.map_or(0, |x| x as *const EventConfig as usize), self.run.as_ptr() as usize, self.event.as_event().key())
+    }
+
+    // env of the job, with keys renamed by envconf
+    pub fn env<'s>(&'s self, envconf: Option<&'s EventEnvMap>) -> Result<HashMap<&'s str, String>, Error> {
+        let event = self.event.as_event();
+        let mut env = match self.config {
+            Some(c) => event.make_env(c.remap.as_ref(), c.float)?,
+            None => event.make_env(None, false)?,
+        };
+        if let Some(s) = self.state {
+            env.state = s.to_string();
+        }
+        if let Some((delta, value)) = self.relative {
+            env.delta = delta.to_string();
+            env.value = value.to_string();
+        }
+        if let Some(tick) = self.tick {
+            env.tick = tick.to_string();
+        }
+        let mut env = env.to_map(envconf);
+        for x in self.config.iter().flat_map(|c| &c.remaps) {
+            env.insert(&x.key, event.remapped(Some(&x.remap), x.float));
+        }
+        for (k, v) in self.vars.iter() {
+            env.insert(k, v.to_string());
+        }
+        Ok(env)
+    }
+
+    pub fn run(&self) -> Result<(), Error> {
+        for r in self.run {
+            r.run(self.env(r.envconf.as_ref())?)?;
+        }
+        Ok(())
+    }
+}
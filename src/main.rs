@@ -5,6 +5,13 @@ pub mod config;
 pub mod run;
 pub mod event;
 pub mod eventmap;
+pub mod gesture;
+pub mod job;
+pub mod pipe;
+#[cfg(feature = "script")]
+pub mod script;
+pub mod queue;
+pub mod state;
 pub mod midi;
 pub mod util;
 pub mod cli;
@@ -34,10 +41,36 @@ fn main() {
     let map_file = err_handle(
         c.map_file.ok_or(Error::NoArgument)
     );
+    // last loaded config, its hooks run on exit and on errors
+    let mut conf: Option<Config> = None;
+    let mut reason = "start";
     loop {
-        err_handle(
-            run_file(&map_file)
-        );
+        let r = load_file(&map_file).and_then(|c| {
+            let c = conf.insert(c);
+            match reason {
+                "start" => run::run_hook(&c.on_start, &map_file, reason, None),
+                _ => run::run_hook(&c.on_reload, &map_file, reason, None),
+            }
+            run_config(c)
+        });
+        match r {
+            Ok(run::Stop::Reload) => reason = "reload",
+            Ok(run::Stop::Exit) => {
+                if let Some(c) = &conf {
+                    run::run_hook(&c.on_exit, &map_file, "signal", None);
+                }
+                return;
+            }
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                if let Some(c) = &conf {
+                    let err = err.to_string();
+                    run::run_hook(&c.on_error, &map_file, "error", Some(&err));
+                    run::run_hook(&c.on_exit, &map_file, "error", Some(&err));
+                }
+                std::process::exit(1);
+            }
+        }
     }
 }
 
@@ -54,13 +87,16 @@ where
     }
 }
 
-fn run_file(filepath: &Path) -> Result<(), Error> {
+fn load_file(filepath: &Path) -> Result<Config, Error> {
     println!("Load file {}", filepath.to_str().unwrap_or("<unknown>"));
     let dat = std::fs::read( filepath )?;
-    let conf = Config::try_from(&dat[..])?;
+    Config::try_from(&dat[..])
+}
+
+fn run_config(conf: &Config) -> Result<run::Stop, Error> {
     let mut handler = match conf.driver {
         Some(v) => MidiHandler::new_with_driver(constant::CLIENT_NAME, v),
         None => MidiHandler::new(constant::CLIENT_NAME),
     }?;
-    handler.builder_handler(run::RunConfigBuilder, &conf)
+    handler.builder_handler(run::RunConfigBuilder, conf)
 }
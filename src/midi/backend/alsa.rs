@@ -273,8 +273,7 @@ impl MidiInputAlsa {
                 EventType::PortUnsubscribed |
                 EventType::Qframe |
                 EventType::Tick |
-                EventType::Clock |
-                EventType::Sensing => false,
+                EventType::Clock => false,
                 EventType::Sysex => {
                     message.extend_from_slice(ev.get_ext().unwrap());
                     *continue_sysex = *message.last().unwrap() != 0xF7;
@@ -3,13 +3,16 @@ use crate::{Error, constant};
 use crate::config::DeviceConfig;
 use crate::eventmap::EventMap;
 use crate::event::{Event, EventBuf};
+use crate::job::Job;
+use crate::pipe::{self, Pipes};
+use crate::queue::JobQueue;
+use crate::state::DeviceState;
 
 use std::str::FromStr;
 use std::thread;
 use std::time::{SystemTime, Instant};
 use std::sync::{mpsc, Mutex, Arc};
-
-use queues::{CircularBuffer, IsQueue};
+use std::sync::mpsc::RecvTimeoutError;
 
 use super::{PortFilter, MidiPort};
 
@@ -50,7 +53,8 @@ where
     fn new(client_name: &str) -> Result<Self, Error>;
     fn ports(&self) -> Result<Vec<MidiPort<Self::DeviceAddr>>, Error>;
     fn try_connect(&self, port: MidiPort<Self::DeviceAddr>, filter: PortFilter<Self::DeviceAddr> ) -> Result<Option<Self>, Error>;
-    fn run(&mut self, conf: &DeviceConfig, eventmap: &EventMap, trs: (mpsc::Sender<bool>, mpsc::Receiver<bool>)) -> Result<(), Error>;
+    // returns false if the device was already disconnected by the Active Sensing watchdog
+    fn run(&mut self, conf: &DeviceConfig, eventmap: &EventMap, port: &str, pipes: &Pipes, trs: (mpsc::Sender<bool>, mpsc::Receiver<bool>)) -> Result<bool, Error>;
     fn device_events(&mut self, ts: mpsc::Sender<Option<MidiPort<Self::DeviceAddr>>>, ss: (mpsc::Sender<bool>,mpsc::Receiver<bool>)) -> Result<(), Error>;
 }
 
@@ -76,7 +80,7 @@ where
         let portmap = self.ports()?;
         let pv = self.filter_ports(portmap, PortFilter::Addr(port.addr));
         let pv = self.filter_ports(pv, filter);
-        if pv.len() > 0 {
+        if !pv.is_empty() {
             let port = &pv[0];
             let mut v = T::new(constant::CLIENT_NAME_HANDLER)?;
             v.connect(&port.addr, constant::CLIENT_NAME_HANDLER)?;
@@ -91,60 +95,131 @@ where
         self.device_events(ts, ss)
     }
 
-    fn run(&mut self, conf: &DeviceConfig, eventmap: &EventMap, (ts, rs): (mpsc::Sender<bool>, mpsc::Receiver<bool>)) -> Result<(), Error> {
-        thread::scope(|s| -> Result<(), Error> {
-
-            // parking signal for runner, true = stop
-            let (pts,prs) = mpsc::channel::<bool>();
-    
-            // event queue populated by the main thread and consumed by the exec thread
-            let evq = Arc::new(Mutex::new(CircularBuffer::<EventBuf>::new(conf.queue_length)));
-    
-            // background execution loop
-            let rq = evq.clone();
-            let exec_thread = s.spawn(move || -> Result<(),Error> {
-                loop {
-                    if prs.recv()? {
-                        break;
-                    }
+    fn run(&mut self, conf: &DeviceConfig, eventmap: &EventMap, port: &str, global_pipes: &Pipes, (ts, rs): (mpsc::Sender<bool>, mpsc::Receiver<bool>)) -> Result<bool, Error> {
+        // pipe processes of this connection, stopped once executors are done
+        let pipes = Pipes::new(&conf.pipes);
+        thread::scope(|s| -> Result<bool, Error> {
+
+            // one execution loop per executor, with its own queue and interval
+            let mut executors = Vec::new();
+            let mut exec_threads = Vec::new();
+            for qc in eventmap.executors() {
+                // parking signal for runner, true = stop
+                let (pts,prs) = mpsc::channel::<bool>();
+
+                // queue of triggered jobs populated by the dispatch thread and consumed by the exec thread
+                let evq = Arc::new(Mutex::new(JobQueue::new(qc)));
+
+                // background execution loop
+                let rq = evq.clone();
+                let interval = qc.interval;
+                let pipes = &pipes;
+                exec_threads.push(s.spawn(move || -> Result<(),Error> {
                     loop {
-                        // nest the lock into a scope to release it before run
-                        let (ev,start): (EventBuf,Instant) = {
-                            let mut evq = rq.lock().unwrap();
-                            if evq.size() > 0 {
-                                (evq.remove().unwrap(), Instant::now())
-                            } else {
-                                break;
+                        if prs.recv()? {
+                            break;
+                        }
+                        loop {
+                            // nest the lock into a scope to release it before run
+                            let (job,start): (Job,Instant) = {
+                                match rq.lock().unwrap().pop() {
+                                    Some(job) => (job, Instant::now()),
+                                    None => break,
+                                }
+                            };
+                            pipe::send(&job, pipes, global_pipes);
+                            job.run().unwrap_or_else(|e| eprintln!("ERROR: error on run: {}", e) );
+                            // wait until interval has been reached
+                            let elapsed_time = start.elapsed();
+                            if elapsed_time < interval {
+                                thread::sleep(interval - elapsed_time);
                             }
-                        };
-                        eventmap.run_event(&ev.as_event()).unwrap_or_else(|e| eprintln!("ERROR: error on run: {}", e) );
-                        // wait until interval has been reached
-                        let elapsed_time = start.elapsed();
-                        if elapsed_time < conf.interval {
-                            thread::sleep(conf.interval - elapsed_time);
                         }
                     }
+                    Ok(())
+                }));
+                executors.push((evq, pts));
+            }
+
+            // events sent by the input thread to the dispatch thread
+            let (tev,rev) = mpsc::channel::<EventBuf>();
+
+            // matching loop, keeps track of every event in device state and fires timed triggers
+            let dexec = executors.clone();
+            let dispatch_thread = s.spawn(move || {
+                let mut state = DeviceState::new();
+                state.set_port(port);
+                state.set_page(eventmap.default_page());
+                if let Some(vars) = eventmap.vars() {
+                    state.set_vars(vars);
                 }
-                Ok(())
+                eventmap.start(SystemTime::now(), &mut state);
+                loop {
+                    let jobs = match eventmap.deadline(&state) {
+                        Some(deadline) => {
+                            match rev.recv_timeout(deadline.duration_since(SystemTime::now()).unwrap_or_default()) {
+                                Ok(event) => eventmap.match_event(&event, &mut state),
+                                // don't tick before deadline in case of clock skew
+                                Err(RecvTimeoutError::Timeout) => eventmap.tick(SystemTime::now().max(deadline), &mut state),
+                                Err(RecvTimeoutError::Disconnected) => break,
+                            }
+                        }
+                        None => {
+                            match rev.recv() {
+                                Ok(event) => eventmap.match_event(&event, &mut state),
+                                Err(_) => break,
+                            }
+                        }
+                    };
+                    if jobs.is_empty() {
+                        continue;
+                    }
+                    let mut woken = vec![false; dexec.len()];
+                    for job in jobs {
+                        let i = eventmap.executor(&job);
+                        let mut evq = dexec[i].0.lock().unwrap();
+                        if let Some(dropped) = evq.push(job) {
+                            eprintln!("WARN: {}: queue full, dropped event {} ({} dropped)", constant::CLIENT_NAME, dropped.event, evq.dropped());
+                        }
+                        woken[i] = true;
+                    }
+                    for (i, (_, pts)) in dexec.iter().enumerate() {
+                        if woken[i] {
+                            pts.send(false).expect("unexpected write error");
+                        }
+                    }
+                }
+                !eventmap.disconnected(&state)
             });
     
-            self.handle_input(|_,m,t,(evq,pts)| {
+            self.handle_input(|_,m,t,tev| {
                 let mut event: EventBuf = Event::from(m).into();
                 event.timestamp = t;
-                if conf.log {
+                if conf.log && !event.as_event().is_sensing() {
                     println!("{}: event: {}", constant::CLIENT_NAME, event);
                 }
-                let mut evq = evq.lock().unwrap();
-                evq.add(event).unwrap();
-                pts.send(false).expect("unexpected write error");
-            }, (ts,rs), (evq,pts.clone()))?;
+                tev.send(event).expect("unexpected write error");
+            }, (ts,rs), tev)?;
     
-            pts.send(true).expect("unexpected write error");
-            let _ = exec_thread.join();
+            // input sender has been dropped, dispatch thread stops
+            let connected = dispatch_thread.join().unwrap_or(true);
+            for (_, pts) in &executors {
+                pts.send(true).expect("unexpected write error");
+            }
+            for t in exec_threads {
+                let _ = t.join();
+            }
+
+            let (dropped, coalesced) = executors.iter().fold((0, 0), |(d, c), (evq, _)| {
+                let evq = evq.lock().unwrap();
+                (d + evq.dropped(), c + evq.coalesced())
+            });
+            if conf.log && (dropped > 0 || coalesced > 0) {
+                println!("{}: queue: {} events dropped, {} coalesced", constant::CLIENT_NAME, dropped, coalesced);
+            }
     
-            Ok(())
+            Ok(connected)
     
-        })?;
-        Ok(())
+        })
     }
 }
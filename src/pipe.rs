@@ -0,0 +1,257 @@
+use std::collections::BTreeMap;
+use std::io::{ErrorKind, Write};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::config::pipe::{PipeConfig, Restart};
+use crate::job::Job;
+use crate::Error;
+
+// time given to a process to exit once its stdin is closed
+const STOP_TIMEOUT: Duration = Duration::from_secs(1);
+// lines waiting to be written to a process, new ones are dropped past it
+const QUEUE_LENGTH: usize = 1024;
+// minimum time between two starts of a process
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+// Pipe config with its writer thread, which owns the process.
+// A process not reading its stdin only blocks its own thread
+#[derive(Debug)]
+pub struct Pipe {
+    config: PipeConfig,
+    tx: Option<SyncSender<String>>,
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+    // queue was full on the last send, to warn once per overflow
+    full: AtomicBool,
+}
+
+#[derive(Debug,Default)]
+struct Shared {
+    // pid of the running process, 0 if none
+    pid: AtomicU32,
+    // stop writing and don't restart, set if the process blocks stopping
+    stopping: AtomicBool,
+}
+
+// Running process of a pipe config
+#[derive(Debug)]
+struct Process {
+    config: PipeConfig,
+    child: Option<Child>,
+    shared: Arc<Shared>,
+    started: Instant,
+    // exited and to be started again once RESTART_DELAY has elapsed
+    restart: bool,
+}
+
+// Pipes of a device connection or of the config, started on creation and stopped on drop
+#[derive(Debug,Default)]
+pub struct Pipes(Vec<(String, Pipe)>);
+
+impl Pipes {
+    pub fn new(configs: &[PipeConfig]) -> Self {
+        Pipes(configs.iter().map(|x| (x.name.clone(), Pipe::new(x.clone()))).collect())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Pipe> {
+        self.0.iter().find(|x| x.0 == name).map(|x| &x.1)
+    }
+}
+
+// write job to the pipes of its event config, device pipes hide global ones
+pub fn send(job: &Job, device: &Pipes, global: &Pipes) {
+    for name in job.config.iter().flat_map(|c| &c.pipe) {
+        if let Some(p) = device.get(name).or_else(|| global.get(name)) {
+            p.send(job).unwrap_or_else(|e| eprintln!("ERROR: error on pipe: {}", e));
+        }
+    }
+}
+
+impl Pipe {
+    fn new(config: PipeConfig) -> Self {
+        let (tx, rx) = mpsc::sync_channel(QUEUE_LENGTH);
+        let shared = Arc::new(Shared::default());
+        let process = Process {
+            config: config.clone(),
+            child: None,
+            shared: shared.clone(),
+            started: Instant::now(),
+            restart: false,
+        };
+        Pipe {
+            config,
+            tx: Some(tx),
+            shared,
+            thread: Some(thread::spawn(move || process.run(rx))),
+            full: AtomicBool::new(false),
+        }
+    }
+
+    fn line(&self, job: &Job) -> Result<String, Error> {
+        let mut env = job.env(None)?;
+        env.insert("type", job.event.r#type.to_string());
+        let mut line = match &self.config.template {
+            Some(t) => t.render(&env),
+            None => {
+                // unset values like the raw bytes of timed jobs are left out
+                let m: BTreeMap<&str, String> = env.into_iter().filter(|(_, v)| !v.is_empty()).collect();
+                serde_json::to_string(&m).map_err(|_| Error::Pipe)?
+            }
+        };
+        line.push('\n');
+        Ok(line)
+    }
+
+    // queue job for the writer thread, dropped if the queue is full
+    pub fn send(&self, job: &Job) -> Result<(), Error> {
+        let line = self.line(job)?;
+        match self.tx.as_ref().map(|x| x.try_send(line)) {
+            Some(Err(TrySendError::Full(_))) => {
+                if !self.full.swap(true, Ordering::Relaxed) {
+                    eprintln!("WARN: pipe '{}' is full, dropping events", self.config.name);
+                }
+                Ok(())
+            }
+            Some(Ok(())) => {
+                self.full.store(false, Ordering::Relaxed);
+                Ok(())
+            }
+            _ => Err(Error::PipeNotRunning(self.config.name.clone())),
+        }
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        // closing the queue stops the writer thread and its process
+        drop(self.tx.take());
+        if let Some(t) = self.thread.take() {
+            let start = Instant::now();
+            while !t.is_finished() && start.elapsed() < STOP_TIMEOUT {
+                thread::sleep(Duration::from_millis(10));
+            }
+            // the writer is blocked on a process not reading its stdin
+            self.shared.stopping.store(true, Ordering::SeqCst);
+            let pid = self.shared.pid.load(Ordering::SeqCst);
+            if !t.is_finished() && pid != 0 {
+                // SAFETY: kill has no memory effect, pid is only set while the child is not reaped
+                unsafe {
+                    libc::kill(pid as libc::pid_t, libc::SIGKILL);
+                }
+            }
+            let _ = t.join();
+        }
+    }
+}
+
+impl Process {
+    // write queued lines until the queue is closed, then stop the process
+    fn run(mut self, rx: Receiver<String>) {
+        self.spawn();
+        for line in rx {
+            if self.shared.stopping.load(Ordering::SeqCst) {
+                break;
+            }
+            self.write(&line).unwrap_or_else(|e| eprintln!("ERROR: error on pipe: {}", e));
+        }
+        self.stop();
+    }
+
+    fn spawn(&mut self) {
+        let args = &self.config.args;
+        self.started = Instant::now();
+        self.restart = false;
+        match Command::new(&args[0]).args(&args[1..]).stdin(Stdio::piped()).spawn() {
+            Ok(c) => {
+                self.shared.pid.store(c.id(), Ordering::SeqCst);
+                self.child = Some(c);
+            }
+            Err(e) => eprintln!("ERROR: failed to start pipe '{}': {}", self.config.name, e),
+        }
+    }
+
+    fn reap(&mut self) {
+        self.shared.pid.store(0, Ordering::SeqCst);
+        self.child = None;
+    }
+
+    // whether the process is running, restarts it according to policy if it exited
+    fn alive(&mut self) -> bool {
+        match self.child.as_mut().map(|c| c.try_wait()) {
+            Some(Ok(None)) => return true,
+            Some(Ok(Some(status))) => {
+                self.reap();
+                eprintln!("WARN: pipe '{}' exited: {}", self.config.name, status);
+                self.restart = self.should_restart(status);
+            }
+            Some(Err(e)) => {
+                eprintln!("ERROR: pipe '{}': {}", self.config.name, e);
+                return false;
+            }
+            None => (),
+        }
+        // a process exiting at once isn't started again on every event
+        if self.restart && self.started.elapsed() >= RESTART_DELAY && !self.shared.stopping.load(Ordering::SeqCst) {
+            self.spawn();
+        }
+        self.child.is_some()
+    }
+
+    fn should_restart(&self, status: ExitStatus) -> bool {
+        match self.config.restart {
+            Restart::Never => false,
+            Restart::OnFailure => !status.success(),
+            Restart::Always => true,
+        }
+    }
+
+    fn write(&mut self, line: &str) -> Result<(), Error> {
+        // one retry with a restarted process if it closed its stdin
+        for _ in 0..2 {
+            if !self.alive() {
+                break;
+            }
+            let stdin = self.child.as_mut().and_then(|c| c.stdin.as_mut()).ok_or(Error::Pipe)?;
+            match stdin.write_all(line.as_bytes()).and_then(|_| stdin.flush()) {
+                Ok(()) => return Ok(()),
+                Err(e) if e.kind() == ErrorKind::BrokenPipe => {
+                    // process is exiting or stopped reading, make sure it is dead
+                    if let Some(c) = self.child.as_mut() {
+                        let _ = c.kill();
+                        let _ = c.wait();
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        // lines are dropped until the restart delay has elapsed
+        if self.restart {
+            return Ok(());
+        }
+        Err(Error::PipeNotRunning(self.config.name.clone()))
+    }
+
+    fn stop(&mut self) {
+        if let Some(mut c) = self.child.take() {
+            // closing stdin lets the process exit on its own
+            drop(c.stdin.take());
+            let step = Duration::from_millis(10);
+            let mut waited = Duration::ZERO;
+            while let Ok(None) = c.try_wait() {
+                if waited >= STOP_TIMEOUT {
+                    let _ = c.kill();
+                    let _ = c.wait();
+                    break;
+                }
+                thread::sleep(step);
+                waited += step;
+            }
+            self.shared.pid.store(0, Ordering::SeqCst);
+        }
+    }
+}
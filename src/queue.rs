@@ -0,0 +1,191 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::config::{DeviceConfig, EventConfig};
+use crate::config::device::Overflow;
+use crate::job::Job;
+
+// Settings of one executor: its queue and rate limit
+#[derive(Debug,Clone,Copy)]
+pub struct QueueConfig {
+    pub queue_length: usize,
+    pub overflow: Overflow,
+    pub interval: Duration,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            queue_length: 256,
+            overflow: Overflow::default(),
+            interval: Duration::ZERO,
+        }
+    }
+}
+
+impl QueueConfig {
+    // event config needs an executor of its own or of its group
+    pub fn is_set(event: &EventConfig) -> bool {
+        event.group.is_some() || event.queue_length.is_some() || event.overflow.is_some() || event.interval.is_some()
+    }
+
+    // override with the settings of an event config
+    pub fn merge(&mut self, event: &EventConfig) {
+        self.queue_length = event.queue_length.unwrap_or(self.queue_length);
+        self.overflow = event.overflow.unwrap_or(self.overflow);
+        self.interval = event.interval.unwrap_or(self.interval);
+    }
+}
+
+impl From<&DeviceConfig> for QueueConfig {
+    fn from(device: &DeviceConfig) -> Self {
+        Self {
+            queue_length: device.queue_length,
+            overflow: device.overflow,
+            interval: device.interval,
+        }
+    }
+}
+
+// Bounded queue of jobs waiting for execution
+#[derive(Debug)]
+pub struct JobQueue<'a> {
+    jobs: VecDeque<Job<'a>>,
+    capacity: usize,
+    overflow: Overflow,
+    // jobs dropped because the queue was full
+    dropped: u64,
+    // jobs replaced by a newer job of the same key
+    coalesced: u64,
+}
+
+impl<'a> JobQueue<'a> {
+    pub fn new(conf: &QueueConfig) -> Self {
+        Self {
+            jobs: VecDeque::with_capacity(conf.queue_length),
+            capacity: conf.queue_length,
+            overflow: conf.overflow,
+            dropped: 0,
+            coalesced: 0,
+        }
+    }
+
+    // add a job, returns the job dropped by overflow if any
+    pub fn push(&mut self, job: Job<'a>) -> Option<Job<'a>> {
+        if self.overflow == Overflow::Coalesce {
+            let key = job.key();
+            if let Some(x) = self.jobs.iter_mut().find(|x| x.key() == key) {
+                x.coalesce(job);
+                self.coalesced += 1;
+                return None;
+            }
+        }
+        if self.jobs.len() < self.capacity {
+            self.jobs.push_back(job);
+            return None;
+        }
+        self.dropped += 1;
+        match self.overflow {
+            Overflow::DropNewest => Some(job),
+            _ => {
+                self.jobs.push_back(job);
+                self.jobs.pop_front()
+            }
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<Job<'a>> {
+        self.jobs.pop_front()
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    pub fn coalesced(&self) -> u64 {
+        self.coalesced
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::serializer::EventConfigSerializer;
+    use crate::event::{EventBuf, EventType};
+
+    fn event(yaml: &str) -> EventConfig {
+        EventConfig::try_from(serde_yaml::from_str::<EventConfigSerializer>(yaml).unwrap()).unwrap()
+    }
+
+    fn queue(queue_length: usize, overflow: Overflow) -> JobQueue<'static> {
+        JobQueue::new(&QueueConfig { queue_length, overflow, interval: Duration::ZERO })
+    }
+
+    fn job(config: &EventConfig, id: u8, value: u16) -> Job<'_> {
+        let event = EventBuf { r#type: EventType::Controller, channel: 0, id, value, raw: vec![], timestamp: None };
+        Job::new(event, config, &config.run)
+    }
+
+    fn relative(config: &EventConfig, delta: i64, value: i64) -> Job<'_> {
+        job(config, 1, 0).with_relative(delta, value)
+    }
+
+    #[test]
+    fn coalesce_relative() {
+        let c = event("{ type: Controller, relative: twos_complement, run: [ cmd: x ] }");
+        let mut q = queue(4, Overflow::Coalesce);
+        q.push(relative(&c, 1, 1));
+        q.push(relative(&c, 2, 2));
+        q.push(relative(&c, -1, -1));
+        assert_eq!(q.coalesced(), 2);
+        assert_eq!(q.pop().unwrap().relative, Some((2, 2)));
+        assert!(q.pop().is_none());
+        // accumulated values are the latest one
+        let c = event("{ type: Controller, relative: twos_complement, accumulate: 0:100, run: [ cmd: x ] }");
+        q.push(relative(&c, 1, 11));
+        q.push(relative(&c, 2, 13));
+        assert_eq!(q.pop().unwrap().relative, Some((3, 13)));
+    }
+
+    fn values(q: &mut JobQueue) -> Vec<(u8, u16)> {
+        std::iter::from_fn(|| q.pop()).map(|j| (j.event.id, j.event.value)).collect()
+    }
+
+    #[test]
+    fn drop_oldest() {
+        let c = event("{ type: Controller, run: [ cmd: x ] }");
+        let mut q = queue(2, Overflow::DropOldest);
+        assert!(q.push(job(&c, 1, 1)).is_none());
+        assert!(q.push(job(&c, 1, 2)).is_none());
+        assert_eq!(q.push(job(&c, 1, 3)).map(|j| j.event.value), Some(1));
+        assert_eq!(q.dropped(), 1);
+        assert_eq!(values(&mut q), [(1, 2), (1, 3)]);
+    }
+
+    #[test]
+    fn drop_newest() {
+        let c = event("{ type: Controller, run: [ cmd: x ] }");
+        let mut q = queue(2, Overflow::DropNewest);
+        q.push(job(&c, 1, 1));
+        q.push(job(&c, 1, 2));
+        assert_eq!(q.push(job(&c, 1, 3)).map(|j| j.event.value), Some(3));
+        assert_eq!(q.dropped(), 1);
+        assert_eq!(values(&mut q), [(1, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn coalesce() {
+        let (c, d) = (event("{ type: Controller, run: [ cmd: x ] }"), event("{ type: Controller, run: [ cmd: y ] }"));
+        let mut q = queue(3, Overflow::Coalesce);
+        q.push(job(&c, 1, 1));
+        q.push(job(&c, 2, 1));
+        q.push(job(&d, 1, 1));
+        // same config, type, channel and id: replaced in place
+        assert!(q.push(job(&c, 1, 2)).is_none());
+        assert_eq!(q.coalesced(), 1);
+        assert_eq!(q.dropped(), 0);
+        // other keys drop the oldest when full
+        assert_eq!(q.push(job(&c, 3, 1)).map(|j| (j.event.id, j.event.value)), Some((1, 2)));
+        assert_eq!(values(&mut q), [(2, 1), (1, 1), (3, 1)]);
+    }
+}
@@ -1,15 +1,24 @@
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
 use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
 use std::thread;
 use std::sync::{Mutex,Arc};
+use std::sync::atomic::{AtomicBool,Ordering};
+use std::time::SystemTime;
 
-use libc::SIGUSR1;
+use libc::{SIGUSR1,SIGINT,SIGTERM};
 use signal_hook::iterator::Signals;
 
 use crate::util::InternalTryFrom;
 use crate::{Error, constant};
 use crate::midi::{PortFilter,MidiInputHandler, MidiPort, Builder};
-use crate::config::{Config,DeviceConfig};
+use crate::config::{Config,DeviceConfig,RunConfig,TimerConfig};
+use crate::job::Job;
+use crate::pipe::Pipes;
 use crate::eventmap::EventMap;
+use crate::state::init_global_vars;
 use crate::midi::builder::builder;
 
 type DeviceRunItem<'a> = (&'a DeviceConfig, EventMap<'a>, Option<Arc<Mutex<(u32, u32)>>>);
@@ -27,7 +36,53 @@ pub fn cross_shell(cmd: &str) -> Vec<String> {
 }
 
 builder!(ListDevicesBuilder, list_devices, (), Result<(), Error>);
-builder!(RunConfigBuilder, run_config, &Config, Result<(), Error>);
+builder!(RunConfigBuilder, run_config, &Config, Result<Stop, Error>);
+
+// Why a config stopped running
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Stop {
+    // SIGUSR1: load the config file again
+    Reload,
+    // SIGINT or SIGTERM
+    Exit,
+}
+
+// run a top-level hook, with the config path, the reason and the error if any in env
+pub fn run_hook(run: &[RunConfig], path: &Path, reason: &str, error: Option<&str>) {
+    for r in run {
+        let mut env = HashMap::new();
+        env.insert("config", path.to_string_lossy().to_string());
+        env.insert("reason", reason.to_string());
+        if let Some(e) = error {
+            env.insert("error", e.to_string());
+        }
+        if let Err(e) = r.run(env) {
+            eprintln!("ERROR: error on run: {}", e);
+        }
+    }
+}
+
+// run top-level timers until stop is signaled or dropped
+fn run_timers(timers: &[TimerConfig], stop: mpsc::Receiver<()>) {
+    let start = SystemTime::now();
+    let mut next: Vec<(SystemTime, u64)> = timers.iter().map(|x| (start + x.interval, 0)).collect();
+    while let Some(deadline) = next.iter().map(|x| x.0).min() {
+        match stop.recv_timeout(deadline.duration_since(SystemTime::now()).unwrap_or_default()) {
+            Err(RecvTimeoutError::Timeout) => (),
+            _ => break,
+        }
+        let t = SystemTime::now().max(deadline);
+        for (timer, (n, tick)) in timers.iter().zip(next.iter_mut()) {
+            if *n <= t {
+                while *n <= t {
+                    *n += timer.interval;
+                }
+                *tick += 1;
+                Job::timed(&timer.run, t).with_tick(*tick).run().unwrap_or_else(|e| eprintln!("ERROR: error on run: {}", e));
+            }
+        }
+    }
+}
 
 pub fn list_devices<T>(input: &T, _: ()) -> Result<(), Error>
 where
@@ -42,11 +97,13 @@ where
     Ok(())
 }
 
-pub fn run_config<T>(input: &T, conf: &Config) -> Result<(), Error>
+pub fn run_config<T>(input: &T, conf: &Config) -> Result<Stop, Error>
 where
     T: MidiInputHandler+Send+'static,
     <T as MidiInputHandler>::DeviceAddr: 'static+std::fmt::Display+InternalTryFrom<String>,
 {
+    init_global_vars(&conf.vars);
+    let pipes = Pipes::new(&conf.pipes);
     let cfevmap: Vec<DeviceRunItem> = conf.devices.iter().map(|x|
         (x, EventMap::from(x),
             x.max_connections.map(|v| (Arc::new(Mutex::new((0,v)))))
@@ -58,26 +115,32 @@ where
 
     let ntsd = tsd.clone();
     let ntdev = tdev.clone();
-    let mut signals = Signals::new(&[SIGUSR1])?;
+    let exit = Arc::new(AtomicBool::new(false));
+    let nexit = exit.clone();
+    let mut signals = Signals::new([SIGUSR1, SIGINT, SIGTERM])?;
     let _signal_thread = thread::spawn(move || {
-        for sig in signals.forever() {
+        if let Some(sig) = signals.forever().next() {
             match sig {
-                10 => {
-                    println!("Recieved SIGUSR1, reloading config file");
-                    ntsd.send(true).unwrap();
-                    ntdev.send(None).unwrap();
-                    break;
+                SIGUSR1 => println!("Recieved SIGUSR1, reloading config file"),
+                _ => {
+                    println!("Recieved signal {}, exiting", sig);
+                    nexit.store(true, Ordering::SeqCst);
                 }
-                _ => (),
             }
+            // stop devices, disconnect commands run before returning
+            ntsd.send(true).unwrap();
+            ntdev.send(None).unwrap();
         }
     });
 
     thread::scope(|s| -> Result<(), Error> {
+        let (tts, trs) = mpsc::channel::<()>();
+        let timer_thread = s.spawn(move || run_timers(&conf.timers, trs));
+
         let mut threads: Vec<DeviceRunResult> = Vec::new();
         let ports = input.ports()?;
         for p in ports {
-            if let Some(v) = try_connect_process(input, s, &p, &cfevmap)? { threads.push(v) }
+            if let Some(v) = try_connect_process(input, s, &p, &cfevmap, &pipes)? { threads.push(v) }
         }
 
         let event_thread = s.spawn(move || {
@@ -96,16 +159,22 @@ where
             if conf.log {
                 println!("{}: device connect: {}", constant::CLIENT_NAME, p);
             }
-            if let Some(v) = try_connect_process(input, s, &p, &cfevmap)? { threads.push(v) }
+            if let Some(v) = try_connect_process(input, s, &p, &cfevmap, &pipes)? { threads.push(v) }
         };
-        event_thread.join().unwrap()?;
+        // devices are stopped and disconnected before an error of the event thread is returned
+        let r = event_thread.join().unwrap_or(Err(Error::Panic));
+        drop(tts);
+        let _ = timer_thread.join();
         for (thread,ss) in threads {
             let _ = ss.send(true);
-            let _ = thread.join().unwrap().map_err(|e| eprintln!("WARN: error in thread: {}", e));
+            let _ = thread.join().unwrap_or(Err(Error::Panic)).map_err(|e| eprintln!("WARN: error in thread: {}", e));
         }
-        Ok(())
+        r
     })?;
-    Ok(())
+    Ok(match exit.load(Ordering::SeqCst) {
+        true => Stop::Exit,
+        false => Stop::Reload,
+    })
 }
 
 fn try_connect_process<'a, T>(
@@ -113,11 +182,12 @@ fn try_connect_process<'a, T>(
     s: &'a thread::Scope<'a, '_>,
     p: &MidiPort<T::DeviceAddr>,
     cfevmap: &'a[DeviceRunItem<'a>],
+    pipes: &'a Pipes,
     )
         -> Result<Option<DeviceRunResult<'a>>, Error>
 where
     T: MidiInputHandler+Send+'static,
-    <T as MidiInputHandler>::DeviceAddr: 'static+InternalTryFrom<String>,
+    <T as MidiInputHandler>::DeviceAddr: 'static+std::fmt::Display+InternalTryFrom<String>,
 {
     for (dev, eventmap, counter) in cfevmap {
         // device counter is full
@@ -138,17 +208,26 @@ where
             let (sts,srs) = mpsc::channel::<bool>();
             let mm = counter.as_ref().map(Arc::clone);
             let nsts = sts.clone();
+            let port = p.addr.to_string();
             let t = s.spawn( move || -> Result<(), Error> {
-                dev.run_connect()?;
-                // blocking process
-                c.run(dev, eventmap, (nsts,srs))?;
+                let r = dev.run_connect().and_then(|_| {
+                    // blocking process, a panic of its threads is turned into an error
+                    let r = panic::catch_unwind(AssertUnwindSafe(|| c.run(dev, eventmap, &port, pipes, (nsts,srs))))
+                        .unwrap_or(Err(Error::Panic));
+                    // disconnect commands run whatever the device returned,
+                    // unless Active Sensing loss already ran them
+                    let d = match r {
+                        Ok(false) => Ok(()),
+                        _ => dev.run_disconnect().map(|_| ()),
+                    };
+                    r.and(d)
+                });
                 // decrease device counter
                 if let Some(m) = mm {
                     let mut m = m.lock().unwrap();
                     m.0 -= 1;
                 }
-                dev.run_disconnect()?;
-                Ok(())
+                r
             });
             return Ok(Some((t, sts)));
         }
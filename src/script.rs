@@ -0,0 +1,125 @@
+use std::process::Command;
+use std::str::FromStr;
+use std::thread;
+
+use crate::config::EventConfig;
+use crate::error::ConfigError;
+use crate::event::Event;
+use crate::state::DeviceState;
+
+use lazy_static::lazy_static;
+use rhai::{Array, Dynamic, Engine, Map, Scope, AST};
+
+lazy_static! {
+    static ref ENGINE: Engine = engine();
+}
+
+// scripts run on the dispatch thread of the device, keep them short
+const MAX_OPERATIONS: u64 = 100_000;
+const MAX_CALL_LEVELS: usize = 32;
+const MAX_EXPR_DEPTH: usize = 64;
+const MAX_SIZE: usize = 10_000;
+
+fn engine() -> Engine {
+    let mut e = Engine::new();
+    e.set_max_operations(MAX_OPERATIONS);
+    e.set_max_call_levels(MAX_CALL_LEVELS);
+    e.set_max_expr_depths(MAX_EXPR_DEPTH, MAX_EXPR_DEPTH);
+    e.set_max_string_size(MAX_SIZE);
+    e.set_max_array_size(MAX_SIZE);
+    e.set_max_map_size(MAX_SIZE);
+    e.register_fn("command", |cmd: &str| spawn(crate::run::cross_shell(cmd)));
+    e.register_fn("command", |args: Array| spawn(args.into_iter().map(|x| x.to_string()).collect()));
+    e
+}
+
+// start a command without waiting for it
+fn spawn(args: Vec<String>) {
+    if args.is_empty() {
+        return;
+    }
+    let mut c = Command::new(&args[0]);
+    c.args(&args[1..]);
+    thread::spawn(move || {
+        if let Err(e) = c.status() {
+            eprintln!("ERROR: error on script command: {}", e);
+        }
+    });
+}
+
+// Compiled Rhai script of an event config
+#[derive(Debug,Clone)]
+pub struct Script {
+    ast: AST,
+}
+
+impl FromStr for Script {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ENGINE.compile(s)
+            .map(|ast| Script { ast })
+            .map_err(|e| ConfigError::InvalidScript(e.to_string()))
+    }
+}
+
+// integer or float value of a remapped value
+fn number(s: String) -> Dynamic {
+    match s.parse::<i64>() {
+        Ok(v) => v.into(),
+        Err(_) => s.parse::<f64>().map_or(Dynamic::UNIT, Dynamic::from),
+    }
+}
+
+impl Script {
+    // run with the event, variables and the persistent store of the device in scope.
+    // Returns false if the script failed or returned false
+    pub fn run(&self, event: &Event, config: &EventConfig, state_name: Option<&str>, relative: Option<(i64, i64)>, device: &str, state: &mut DeviceState) -> bool {
+        let mut ev = Map::new();
+        ev.insert("type".into(), event.r#type.to_string().into());
+        ev.insert("channel".into(), (event.channel as i64).into());
+        ev.insert("id".into(), (event.id as i64).into());
+        ev.insert("rawvalue".into(), (event.value as i64).into());
+        ev.insert("value".into(), match relative {
+            Some((_, v)) => v.into(),
+            None => number(event.remapped(config.remap.as_ref(), config.float)),
+        });
+        if let Some((delta, _)) = relative {
+            ev.insert("delta".into(), delta.into());
+        }
+        if let Some(s) = state_name {
+            ev.insert("state".into(), s.into());
+        }
+        for x in &config.remaps {
+            ev.insert(x.key.as_str().into(), number(event.remapped(Some(&x.remap), x.float)));
+        }
+        let vars: Map = state.vars().into_iter().map(|(k, v)| (k.into(), v.into())).collect();
+        let key = config as *const EventConfig as usize;
+
+        let mut scope = Scope::new();
+        scope.push_constant("event", ev);
+        scope.push_constant("device", device.to_string());
+        scope.push("vars", vars.clone());
+        scope.push("store", std::mem::take(state.store(key)));
+        let r = ENGINE.eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast);
+
+        *state.store(key) = scope.get_value("store").unwrap_or_default();
+        // write back changed variables, new ones are ignored
+        let new: Map = scope.get_value("vars").unwrap_or_default();
+        for (k, v) in new {
+            if vars.get(&k).is_some_and(|x| x.as_int().ok() != v.as_int().ok()) {
+                match v.as_int() {
+                    Ok(v) => state.set_var(&k, v),
+                    Err(t) => eprintln!("ERROR: error in script: var '{}' set to {}, expected an integer", k, t),
+                }
+            }
+        }
+        match r {
+            Ok(v) => v.as_bool() != Ok(false),
+            Err(e) => {
+                eprintln!("ERROR: error in script: {}", e);
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,341 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::SystemTime;
+
+use crate::config::event::Cycle;
+use crate::config::var::VarAction;
+use crate::event::{Event, EVENT_TABLE_SIZE};
+use crate::gesture::ButtonState;
+use crate::util::Range;
+
+use lazy_static::lazy_static;
+
+lazy_static! {
+    // cycle steps kept across config reloads, keyed by device port and persist name
+    static ref PERSISTED_CYCLES: Mutex<HashMap<(String, String), usize>> = Mutex::new(HashMap::new());
+    // global variables shared by all devices: (value, initial value), keyed by name
+    static ref GLOBAL_VARS: Mutex<BTreeMap<String, (i64, i64)>> = Mutex::new(BTreeMap::new());
+}
+
+// whether GLOBAL_VARS has entries, to not lock it on every event without global variables
+static HAS_GLOBAL_VARS: AtomicBool = AtomicBool::new(false);
+
+// declare global variables, values of the ones already declared are kept across config reloads
+pub fn init_global_vars(vars: &BTreeMap<String, i64>) {
+    let mut globals = GLOBAL_VARS.lock().unwrap();
+    globals.retain(|k, _| vars.contains_key(k));
+    for (k, v) in vars {
+        let x = globals.entry(k.clone()).or_insert((*v, *v));
+        x.1 = *v;
+    }
+    HAS_GLOBAL_VARS.store(!globals.is_empty(), Ordering::Relaxed);
+}
+
+// Runtime state of one connected device
+#[derive(Debug)]
+pub struct DeviceState {
+    // address of the device port, identifies the device across config reloads
+    port: String,
+    // last value of every control, indexed like the event table
+    values: Box<[Option<u16>]>,
+    // press time of held notes, indexed by channel*128+note
+    notes: Box<[Option<SystemTime>]>,
+    // trigger state of event configs, keyed by (config address, event index)
+    triggers: HashMap<(usize, usize), u16>,
+    // active chords, keyed by (config address, channel)
+    chords: HashSet<(usize, u8)>,
+    // progress of sequences: (next step, time of first step), keyed by config address
+    sequences: HashMap<usize, (usize, SystemTime)>,
+    // gesture tracking of buttons, keyed by (layer, button index)
+    buttons: HashMap<(usize, usize), ButtonState>,
+    // active page, None for the base layer only
+    page: Option<usize>,
+    // page active before the last switch, restored by toggles
+    previous_page: Option<usize>,
+    // button holding a momentary page and the page to restore on its release
+    page_hold: Option<(usize, Option<usize>)>,
+    // current step of toggles and cycles, keyed by config address
+    cycles: HashMap<usize, usize>,
+    // accumulated values of relative controls, keyed by (config address, event index)
+    accumulators: HashMap<(usize, usize), i64>,
+    // device variables: (value, initial value), keyed by name
+    vars: BTreeMap<String, (i64, i64)>,
+    // next run time and number of ticks of timers, indexed like the device timers
+    timers: Vec<(SystemTime, u64)>,
+    // persistent store of scripts, keyed by config address
+    #[cfg(feature = "script")]
+    stores: HashMap<usize, rhai::Map>,
+    // time of the last event, or of connection
+    last_event: Option<SystemTime>,
+    // no event since the idle duration
+    idle: bool,
+    // time of the last Active Sensing, None until the device sends one
+    sensing: Option<SystemTime>,
+    // no Active Sensing since the sensing timeout
+    sensing_lost: bool,
+}
+
+impl Default for DeviceState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DeviceState {
+    pub fn new() -> Self {
+        Self {
+            port: String::new(),
+            values: vec![None; EVENT_TABLE_SIZE].into_boxed_slice(),
+            notes: vec![None; 16*128].into_boxed_slice(),
+            triggers: HashMap::new(),
+            chords: HashSet::new(),
+            sequences: HashMap::new(),
+            buttons: HashMap::new(),
+            page: None,
+            previous_page: None,
+            page_hold: None,
+            cycles: HashMap::new(),
+            accumulators: HashMap::new(),
+            vars: BTreeMap::new(),
+            timers: Vec::new(),
+            #[cfg(feature = "script")]
+            stores: HashMap::new(),
+            last_event: None,
+            idle: false,
+            sensing: None,
+            sensing_lost: false,
+        }
+    }
+
+    pub fn value(&self, index: usize) -> Option<u16> {
+        self.values.get(index).copied().flatten()
+    }
+
+    pub fn update(&mut self, event: &Event) {
+        if let Some(i) = event.index() {
+            self.values[i] = Some(event.value);
+        }
+        if event.id < 128 {
+            let i = event.channel as usize * 128 + event.id as usize;
+            if event.is_note_press() {
+                self.notes[i] = Some(event.timestamp.unwrap_or_else(SystemTime::now));
+            }
+            else if event.is_note_release() {
+                self.notes[i] = None;
+            }
+        }
+    }
+
+    // press time of note if it is held
+    pub fn held(&self, channel: u8, note: u8) -> Option<SystemTime> {
+        self.notes.get(channel as usize * 128 + note as usize).copied().flatten()
+    }
+
+    pub fn held_count(&self, channel: u8) -> usize {
+        let start = channel as usize * 128;
+        self.notes[start..start+128].iter().filter(|x| x.is_some()).count()
+    }
+
+    pub fn trigger(&self, key: (usize, usize)) -> Option<u16> {
+        self.triggers.get(&key).copied()
+    }
+
+    pub fn set_trigger(&mut self, key: (usize, usize), v: u16) {
+        self.triggers.insert(key, v);
+    }
+
+    pub fn chord_active(&self, key: (usize, u8)) -> bool {
+        self.chords.contains(&key)
+    }
+
+    pub fn set_chord_active(&mut self, key: (usize, u8), active: bool) {
+        if active {
+            self.chords.insert(key);
+        }
+        else {
+            self.chords.remove(&key);
+        }
+    }
+
+    pub fn sequence(&self, key: usize) -> Option<(usize, SystemTime)> {
+        self.sequences.get(&key).copied()
+    }
+
+    pub fn set_sequence(&mut self, key: usize, progress: Option<(usize, SystemTime)>) {
+        match progress {
+            Some(v) => self.sequences.insert(key, v),
+            None => self.sequences.remove(&key),
+        };
+    }
+
+    pub fn button(&mut self, key: (usize, usize)) -> &mut ButtonState {
+        self.buttons.entry(key).or_default()
+    }
+
+    pub fn buttons(&self) -> impl Iterator<Item = (&(usize, usize), &ButtonState)> {
+        self.buttons.iter()
+    }
+
+    pub fn buttons_mut(&mut self) -> impl Iterator<Item = (&(usize, usize), &mut ButtonState)> {
+        self.buttons.iter_mut()
+    }
+
+    pub fn set_port(&mut self, port: &str) {
+        self.port = port.to_string();
+    }
+
+    pub fn page(&self) -> Option<usize> {
+        self.page
+    }
+
+    pub fn previous_page(&self) -> Option<usize> {
+        self.previous_page
+    }
+
+    pub fn set_page(&mut self, page: Option<usize>) {
+        if page != self.page {
+            // releases of buttons held on the page left go to the new one, end their gestures
+            if let Some(p) = self.page {
+                self.buttons.retain(|k, _| k.0 != p+1);
+            }
+            self.previous_page = self.page;
+            self.page = page;
+        }
+    }
+
+    pub fn page_hold(&self) -> Option<(usize, Option<usize>)> {
+        self.page_hold
+    }
+
+    pub fn set_page_hold(&mut self, hold: Option<(usize, Option<usize>)>) {
+        self.page_hold = hold;
+    }
+
+    // add delta to an accumulated value clamped to range, starting at its low end
+    pub fn accumulate(&mut self, key: (usize, usize), delta: i64, range: &Range<i64>) -> i64 {
+        let (low, high) = match range.start() <= range.end() {
+            true  => (range.start(), range.end()),
+            false => (range.end(), range.start()),
+        };
+        let v = self.accumulators.entry(key).or_insert(low);
+        *v = (*v + delta).clamp(low, high);
+        *v
+    }
+
+    pub fn set_vars(&mut self, vars: &BTreeMap<String, i64>) {
+        self.vars = vars.iter().map(|(k, v)| (k.clone(), (*v, *v))).collect();
+    }
+
+    // value of a device variable, or of a global one
+    pub fn var(&self, name: &str) -> Option<i64> {
+        match self.vars.get(name) {
+            Some(x) => Some(x.0),
+            None => GLOBAL_VARS.lock().unwrap().get(name).map(|x| x.0),
+        }
+    }
+
+    // apply action to a device variable, or to a global one.
+    // value is the one of the triggering event
+    pub fn apply_var(&mut self, action: &VarAction, value: i64) {
+        self.update_var(&action.name, |v, initial| action.apply(v, initial, value));
+    }
+
+    pub fn set_var(&mut self, name: &str, value: i64) {
+        self.update_var(name, |_, _| value);
+    }
+
+    // set a device variable, or a global one, from its value and initial value
+    fn update_var(&mut self, name: &str, f: impl FnOnce(i64, i64) -> i64) {
+        let mut globals;
+        let x = match self.vars.get_mut(name) {
+            Some(x) => x,
+            None => {
+                globals = GLOBAL_VARS.lock().unwrap();
+                match globals.get_mut(name) {
+                    Some(x) => x,
+                    None => return,
+                }
+            }
+        };
+        x.0 = f(x.0, x.1);
+    }
+
+    // values of all variables, device variables shadow global ones
+    pub fn vars(&self) -> Vec<(String, i64)> {
+        if !HAS_GLOBAL_VARS.load(Ordering::Relaxed) {
+            return self.vars.iter().map(|(k, v)| (k.clone(), v.0)).collect();
+        }
+        let globals = GLOBAL_VARS.lock().unwrap();
+        let globals = globals.iter().filter(|(k, _)| !self.vars.contains_key(*k));
+        self.vars.iter().chain(globals).map(|(k, v)| (k.clone(), v.0)).collect()
+    }
+
+    #[cfg(feature = "script")]
+    pub fn store(&mut self, key: usize) -> &mut rhai::Map {
+        self.stores.entry(key).or_default()
+    }
+
+    pub fn timers(&self) -> &[(SystemTime, u64)] {
+        &self.timers
+    }
+
+    pub fn timers_mut(&mut self) -> &mut [(SystemTime, u64)] {
+        &mut self.timers
+    }
+
+    pub fn set_timers(&mut self, timers: Vec<(SystemTime, u64)>) {
+        self.timers = timers;
+    }
+
+    pub fn last_event(&self) -> Option<SystemTime> {
+        self.last_event
+    }
+
+    pub fn set_last_event(&mut self, t: SystemTime) {
+        self.last_event = Some(t);
+    }
+
+    pub fn idle(&self) -> bool {
+        self.idle
+    }
+
+    pub fn set_idle(&mut self, idle: bool) {
+        self.idle = idle;
+    }
+
+    pub fn sensing(&self) -> Option<SystemTime> {
+        self.sensing
+    }
+
+    pub fn set_sensing(&mut self, t: SystemTime) {
+        self.sensing = Some(t);
+    }
+
+    pub fn sensing_lost(&self) -> bool {
+        self.sensing_lost
+    }
+
+    pub fn set_sensing_lost(&mut self, lost: bool) {
+        self.sensing_lost = lost;
+    }
+
+    // advance a toggle or cycle and return its new step
+    pub fn next_cycle(&mut self, key: usize, cycle: &Cycle) -> usize {
+        let next = |x: Option<&usize>| x.map_or(0, |x| (x+1) % cycle.steps.len());
+        match &cycle.persist {
+            Some(name) => {
+                let mut persisted = PERSISTED_CYCLES.lock().unwrap();
+                let key = (self.port.clone(), name.clone());
+                let step = next(persisted.get(&key));
+                persisted.insert(key, step);
+                step
+            }
+            None => {
+                let step = next(self.cycles.get(&key));
+                self.cycles.insert(key, step);
+                step
+            }
+        }
+    }
+}
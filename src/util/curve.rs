@@ -0,0 +1,203 @@
+use std::str::FromStr;
+
+use crate::error::ConfigError;
+
+use serde::Deserialize;
+
+const EXP_FACTOR: f64 = 4.0;
+// exp(k) overflows past ~709, keep a margin for the intermediate products
+const MAX_FACTOR: f64 = 100.0;
+
+// Shape of a remap, applied on the position in the source range, from 0 to 1
+#[derive(Deserialize,Debug,Clone,PartialEq,Default)]
+#[serde(try_from = "CurveSerializer")]
+pub enum Curve {
+    #[default]
+    Linear,
+    // slow start, steepness factor
+    Exp(f64),
+    // fast start, steepness factor
+    Log(f64),
+    // power of gamma
+    Pow(f64),
+    // smooth start and end
+    SCurve,
+    // quantize to a number of evenly spaced values
+    Steps(u32),
+    // piecewise-linear through (x, y) points, sorted by x
+    Points(Vec<(f64, f64)>),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CurveSerializer {
+    Name(String),
+    Points(Vec<(f64, f64)>),
+}
+
+impl Curve {
+    pub fn is_linear(&self) -> bool {
+        *self == Curve::Linear
+    }
+
+    pub fn apply(&self, x: f64) -> f64 {
+        let x = x.clamp(0.0, 1.0);
+        let y = match self {
+            Curve::Linear => x,
+            Curve::Exp(k) => ((k*x).exp() - 1.0) / (k.exp() - 1.0),
+            Curve::Log(k) => (1.0 + x*(k.exp() - 1.0)).ln() / k,
+            Curve::Pow(g) => x.powf(*g),
+            Curve::SCurve => x*x*(3.0 - 2.0*x),
+            Curve::Steps(n) => {
+                let n = (*n - 1) as f64;
+                (x*n).round() / n
+            }
+            Curve::Points(p) => {
+                let i = p.partition_point(|(px, _)| *px < x);
+                if i == 0 {
+                    return p[0].1;
+                }
+                if i == p.len() {
+                    return p[i-1].1;
+                }
+                let ((x0, y0), (x1, y1)) = (p[i-1], p[i]);
+                y0 + (x - x0)*(y1 - y0)/(x1 - x0)
+            }
+        };
+        // overflows clamp to the bounds, undefined results fall back to linear
+        if y.is_nan() {
+            x
+        }
+        else if y.is_infinite() {
+            y.clamp(0.0, 1.0)
+        }
+        else {
+            y
+        }
+    }
+}
+
+impl FromStr for Curve {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ConfigError::InvalidCurve(s.to_string());
+        let s = s.trim();
+        let (name, arg) = match s.find('(') {
+            Some(i) => {
+                let arg = s[i+1..].strip_suffix(')').ok_or_else(err)?;
+                (s[..i].trim(), Some(arg.trim()))
+            }
+            None => (s, None),
+        };
+        let float = |x: Option<&str>| -> Result<Option<f64>, ConfigError> {
+            match x.map(f64::from_str) {
+                Some(Ok(v)) if v.is_finite() && v > 0.0 => Ok(Some(v)),
+                Some(_) => Err(err()),
+                None => Ok(None),
+            }
+        };
+        match name {
+            "linear"  if arg.is_none() => Ok(Curve::Linear),
+            "s-curve" if arg.is_none() => Ok(Curve::SCurve),
+            "exp" | "log" => {
+                let k = float(arg)?.unwrap_or(EXP_FACTOR);
+                if k > MAX_FACTOR {
+                    return Err(err());
+                }
+                Ok(if name == "exp" { Curve::Exp(k) } else { Curve::Log(k) })
+            }
+            "pow" => Ok(Curve::Pow(float(arg)?.ok_or_else(err)?)),
+            "steps" => match arg.map(u32::from_str) {
+                Some(Ok(n)) if n >= 2 => Ok(Curve::Steps(n)),
+                _ => Err(err()),
+            },
+            _ => Err(err()),
+        }
+    }
+}
+
+impl TryFrom<CurveSerializer> for Curve {
+    type Error = ConfigError;
+    fn try_from(v: CurveSerializer) -> Result<Self, Self::Error> {
+        match v {
+            CurveSerializer::Name(s) => s.parse(),
+            CurveSerializer::Points(p) => {
+                if p.len() < 2 || p.windows(2).any(|w| w[0].0 >= w[1].0) {
+                    return Err(ConfigError::InvalidPoints);
+                }
+                Ok(Curve::Points(p))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn apply_bounds() {
+        let curves = ["linear", "exp", "exp(100)", "log(0.5)", "pow(2)", "s-curve", "steps(4)"];
+        for c in curves.iter().map(|x| x.parse::<Curve>().unwrap()) {
+            assert!(close(c.apply(0.0), 0.0), "{:?}", c);
+            assert!(close(c.apply(1.0), 1.0), "{:?}", c);
+            // input is clamped to the source range
+            assert!(close(c.apply(-1.0), 0.0), "{:?}", c);
+            assert!(close(c.apply(2.0), 1.0), "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn apply_shapes() {
+        assert!(close(Curve::Linear.apply(0.3), 0.3));
+        assert!(Curve::Exp(4.0).apply(0.5) < 0.5);
+        assert!(Curve::Log(4.0).apply(0.5) > 0.5);
+        assert!(close(Curve::Pow(2.0).apply(0.5), 0.25));
+        assert!(close(Curve::SCurve.apply(0.5), 0.5));
+        assert!(Curve::SCurve.apply(0.25) < 0.25);
+        assert!(close(Curve::Steps(3).apply(0.2), 0.0));
+        assert!(close(Curve::Steps(3).apply(0.3), 0.5));
+        assert!(close(Curve::Steps(3).apply(0.8), 1.0));
+    }
+
+    #[test]
+    fn apply_points() {
+        let c = Curve::Points(vec![(0.2, 1.0), (0.6, 0.0), (1.0, 0.5)]);
+        assert!(close(c.apply(0.0), 1.0));
+        assert!(close(c.apply(0.4), 0.5));
+        assert!(close(c.apply(0.8), 0.25));
+        assert!(close(c.apply(1.0), 0.5));
+    }
+
+    #[test]
+    fn apply_non_finite() {
+        // exp(k) overflows for factors past the parsing bound
+        assert!(close(Curve::Exp(800.0).apply(1.0), 1.0));
+        assert!(close(Curve::Exp(800.0).apply(0.5), 0.0));
+        assert!(close(Curve::Log(800.0).apply(0.5), 1.0));
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("linear".parse::<Curve>().unwrap(), Curve::Linear);
+        assert_eq!(" exp ".parse::<Curve>().unwrap(), Curve::Exp(EXP_FACTOR));
+        assert_eq!("log( 2.5 )".parse::<Curve>().unwrap(), Curve::Log(2.5));
+        assert_eq!("steps(8)".parse::<Curve>().unwrap(), Curve::Steps(8));
+        let invalid = ["", "exp(", "exp(0)", "exp(-1)", "exp(101)", "pow", "pow(nan)", "steps(1)", "linear(1)", "sine"];
+        for s in invalid {
+            assert!(s.parse::<Curve>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn points() {
+        assert!(Curve::try_from(CurveSerializer::Points(vec![(0.0, 0.0), (1.0, 1.0)])).is_ok());
+        assert!(Curve::try_from(CurveSerializer::Points(vec![(0.0, 0.0)])).is_err());
+        assert!(Curve::try_from(CurveSerializer::Points(vec![(0.5, 0.0), (0.5, 1.0)])).is_err());
+    }
+}
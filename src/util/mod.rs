@@ -2,10 +2,16 @@
 pub mod smartset;
 pub mod range;
 pub mod remap;
+pub mod note;
+pub mod curve;
+pub mod template;
 
 pub type SmartSet<T> = smartset::SmartSet<T>;
 pub type Range<T> = range::Range<T>;
 pub type Remapper<T> = remap::Remapper<T>;
+pub type Note = note::Note;
+pub type Curve = curve::Curve;
+pub type Template = template::Template;
 
 
 macro_rules! visit_from {
@@ -0,0 +1,128 @@
+use std::str::FromStr;
+use std::fmt;
+
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use thiserror::Error;
+
+#[derive(Error,Debug)]
+#[error("invalid note '{0}'")]
+pub struct NoteError(String);
+
+// MIDI note number, from an integer or a note name: C4, F#2, Bb-1...
+// C4 is middle C (60), C-1 is 0
+#[derive(Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord)]
+pub struct Note(pub u8);
+
+pub fn parse_note(s: &str) -> Result<u8, NoteError> {
+    let err = || NoteError(s.to_string());
+    let s = s.trim();
+    if let Ok(n) = s.parse::<u8>() {
+        return if n < 128 { Ok(n) } else { Err(err()) };
+    }
+    let mut chars = s.chars();
+    let base: i32 = match chars.next().map(|c| c.to_ascii_uppercase()) {
+        Some('C') => 0,
+        Some('D') => 2,
+        Some('E') => 4,
+        Some('F') => 5,
+        Some('G') => 7,
+        Some('A') => 9,
+        Some('B') => 11,
+        _ => return Err(err()),
+    };
+    let rest = chars.as_str();
+    let (alter, octave) = match rest.chars().next() {
+        Some('#') => (1, &rest[1..]),
+        Some('b') => (-1, &rest[1..]),
+        _ => (0, rest),
+    };
+    let octave: i32 = octave.parse().map_err(|_| err())?;
+    let n = (octave+1)*12 + base + alter;
+    if (0..128).contains(&n) {
+        Ok(n as u8)
+    }
+    else {
+        Err(err())
+    }
+}
+
+impl FromStr for Note {
+    type Err = NoteError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_note(s).map(Note)
+    }
+}
+
+struct NoteVisitor;
+
+impl<'de> Visitor<'de> for NoteVisitor {
+    type Value = Note;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a note number or name")
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Note::from_str(&value.to_string()).map_err(serde::de::Error::custom)
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Note::from_str(&value.to_string()).map_err(serde::de::Error::custom)
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Note::from_str(value).map_err(serde::de::Error::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for Note {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(NoteVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert_eq!(parse_note("C4").unwrap(), 60);
+        assert_eq!(parse_note("c4").unwrap(), 60);
+        assert_eq!(parse_note("A4").unwrap(), 69);
+        assert_eq!(parse_note("F#2").unwrap(), 42);
+        assert_eq!(parse_note("Gb2").unwrap(), 42);
+        assert_eq!(parse_note(" bb3 ").unwrap(), 58);
+        assert_eq!(parse_note("C-1").unwrap(), 0);
+        assert_eq!(parse_note("Bb-1").unwrap(), 10);
+        assert_eq!(parse_note("G9").unwrap(), 127);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse_note("0").unwrap(), 0);
+        assert_eq!(parse_note("127").unwrap(), 127);
+        assert!(parse_note("128").is_err());
+    }
+
+    #[test]
+    fn invalid() {
+        for s in ["", "H4", "C", "C#", "Cx4", "C 4", "G#9", "Cb-1", "C-2", "-1"] {
+            assert!(parse_note(s).is_err(), "{}", s);
+        }
+        assert_eq!(parse_note("X1").unwrap_err().to_string(), "invalid note 'X1'");
+    }
+}
@@ -3,7 +3,7 @@ use num::{Num,NumCast,ToPrimitive, Bounded};
 use std::str::FromStr;
 use std::ops;
 
-use super::Range;
+use super::{Range, Curve};
 
 // Trait aliases are unstable
 //trait remapnum = T: Num+ToPrimitive+Copy+PartialOrd + FromStr + ops::Add + ops::Sub + ops::Div + ops::Mul;
@@ -15,6 +15,9 @@ where
 {
     src: Range<T>,
     dst: Range<T>,
+    curve: Curve,
+    // reverse direction of source before the curve
+    invert: bool,
 }
 
 impl<T> Remapper<T>
@@ -34,16 +37,34 @@ where
         Self {
             src,
             dst,
+            curve: Curve::Linear,
+            invert: false,
         }
     }
 
+    pub fn with_curve(mut self, curve: Curve, invert: bool) -> Self {
+        self.curve = curve;
+        self.invert = invert;
+        self
+    }
+
     pub fn remap(&self, v: T) -> T
     {
-        // compute actual value in source type
-        let r: T = (v-self.src.start())*(self.dst.end()-self.dst.start())
-            / (self.src.end()-self.src.start())
-            + self.dst.start();
-        r
+        if self.curve.is_linear() && !self.invert {
+            // compute actual value in source type
+            let r: T = (v-self.src.start())*(self.dst.end()-self.dst.start())
+                / (self.src.end()-self.src.start())
+                + self.dst.start();
+            return r;
+        }
+        // position in source range, shaped by the curve
+        let f = |x: T| x.to_f64().unwrap_or_default();
+        let mut x = (f(v)-f(self.src.start())) / (f(self.src.end())-f(self.src.start()));
+        if self.invert {
+            x = 1.0 - x;
+        }
+        let y = self.curve.apply(x);
+        num::NumCast::from(f(self.dst.start()) + y*(f(self.dst.end())-f(self.dst.start()))).unwrap_or(self.dst.start())
     }
 }
 
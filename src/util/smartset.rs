@@ -1,44 +1,32 @@
 
-use std::collections::BTreeSet;
-
 use std::str::FromStr;
 use std::ops;
 
-use num::{Num,NumCast};
+use num::{Num,NumCast,ToPrimitive};
 
 use thiserror::Error;
 
 // Trait aliases are unstable
 //trait smartsetnum = T: Num+Ord+Copy + std::str::FromStr + ops::AddAssign;
 
-pub fn parse_int_set<T>(s: &str) -> Result<BTreeSet<T>, <T as std::str::FromStr>::Err>
+pub fn parse_int_set<T>(s: &str) -> Result<SmartSet<T>, <T as std::str::FromStr>::Err>
 where
     T: Num+Ord+Copy + std::str::FromStr + ops::AddAssign,
 {
 
-    let mut r: BTreeSet<T> = BTreeSet::new();
+    let mut r: SmartSet<T> = SmartSet::new();
     let parts: Vec<&str> = s.split(',').collect();
     for p in parts {
-        if p.len() > 0 {
-            let mut osep = s.find(':');
+        let p = p.trim();
+        if !p.is_empty() {
+            let mut osep = p.find(':');
             if osep.is_none() {
-                osep = s.find('-');
+                osep = p.find('-');
             }
             if let Some(sep) = osep {
-                let (p1,p2) = (&s[..sep], &s[sep+1..] );
+                let (p1,p2) = (&p[..sep], &p[sep+1..] );
                 let (low,high): (T,T) = ( p1.parse()?, p2.parse()? );
-                let (mut low,high) = match low <= high {
-                    true => (low,high),
-                    false => (high,low),
-                };
-                r.insert(low);
-                loop {
-                    r.insert(low);
-                    if low >= high {
-                        break;
-                    }
-                    low += T::one();
-                }
+                r.insert_range(low, high);
             }
             else {
                 r.insert(p.parse()?);
@@ -48,12 +36,14 @@ where
     Ok(r)
 }
 
-#[derive(Debug,Clone)]
+// Set of integers stored as sorted, non-overlapping and non-adjacent inclusive ranges.
+// Large contiguous sets such as the full u16 value space only take one entry.
+#[derive(Debug,Clone,Default)]
 pub struct SmartSet<T>
 where
     T: Num+Ord+Copy + std::str::FromStr + ops::AddAssign,
 {
-    pub set: BTreeSet<T>
+    ranges: Vec<(T,T)>,
 }
 
 impl<T> SmartSet<T>
@@ -62,16 +52,113 @@ where
 {
     pub fn new() -> Self {
         Self {
-            set: BTreeSet::new(),
+            ranges: Vec::new(),
         }
     }
 
+    pub fn from_range(low: T, high: T) -> Self {
+        let mut r = Self::new();
+        r.insert_range(low, high);
+        r
+    }
+
     pub fn insert(&mut self, v: T) -> bool {
-        self.set.insert(v)
+        self.insert_range(v, v)
+    }
+
+    // insert all values between low and high included, returns true if the set was modified
+    pub fn insert_range(&mut self, low: T, high: T) -> bool {
+        let (mut low, mut high) = match low <= high {
+            true => (low,high),
+            false => (high,low),
+        };
+        // first range that overlaps or touches [low,high]
+        // additions are only evaluated when they can't overflow
+        let start = self.ranges.partition_point(|&(_,e)| e < low && e + T::one() < low);
+        // first range entirely after [low,high]
+        let end = self.ranges.partition_point(|&(s,_)| s <= high || s == high + T::one());
+        if start < end {
+            let (first, last) = (self.ranges[start], self.ranges[end-1]);
+            if end - start == 1 && first.0 <= low && first.1 >= high {
+                // already contained
+                return false;
+            }
+            low = low.min(first.0);
+            high = high.max(last.1);
+        }
+        self.ranges.splice(start..end, [(low,high)]);
+        true
+    }
+
+    pub fn extend(&mut self, other: &SmartSet<T>) {
+        for &(low,high) in &other.ranges {
+            self.insert_range(low, high);
+        }
+    }
+
+    pub fn contains(&self, v: &T) -> bool {
+        let i = self.ranges.partition_point(|&(_,e)| e < *v);
+        i < self.ranges.len() && self.ranges[i].0 <= *v
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn ranges(&self) -> &[(T,T)] {
+        &self.ranges
     }
 
+    pub fn iter(&self) -> Iter<std::iter::Copied<std::slice::Iter<'_, (T,T)>>, T> {
+        Iter::new(self.ranges.iter().copied())
+    }
+}
+
+impl<T> SmartSet<T>
+where
+    T: Num+Ord+Copy+ToPrimitive + std::str::FromStr + ops::AddAssign,
+{
     pub fn len(&self) -> usize {
-        self.set.len()
+        self.ranges.iter().map(|&(low,high)| (high-low).to_usize().unwrap_or(usize::MAX-1) + 1).sum()
+    }
+}
+
+// Expands ranges into individual values
+pub struct Iter<I,T> {
+    ranges: I,
+    current: Option<(T,T)>,
+}
+
+impl<I,T> Iter<I,T>
+where
+    I: Iterator<Item = (T,T)>,
+{
+    fn new(mut ranges: I) -> Self {
+        let current = ranges.next();
+        Self {
+            ranges,
+            current,
+        }
+    }
+}
+
+impl<I,T> Iterator for Iter<I,T>
+where
+    I: Iterator<Item = (T,T)>,
+    T: Num+Ord+Copy + ops::AddAssign,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let (low,high) = self.current.as_mut()?;
+        let v = *low;
+        if *low >= *high {
+            self.current = self.ranges.next();
+        }
+        else {
+            *low += T::one();
+        }
+        Some(v)
     }
 }
 
@@ -81,11 +168,24 @@ where
 {
     fn from(i: T) -> Self {
         SmartSet {
-            set: BTreeSet::from([i]),
+            ranges: Vec::from([(i,i)]),
         }
     }
 }
 
+impl<T> FromIterator<T> for SmartSet<T>
+where
+    T: Num+Ord+Copy + std::str::FromStr + ops::AddAssign,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut r = SmartSet::new();
+        for v in iter {
+            r.insert(v);
+        }
+        r
+    }
+}
+
 #[derive(Error,Debug)]
 pub enum Error<T>
 where
@@ -113,11 +213,9 @@ where
     type Error = Error<U>;
     fn i_try_from(i: U) -> Result<Self, Self::Error> {
         // let mut r = SmartSet::<T>::new();
-        match num::NumCast::from(i) {
+        match <T as NumCast>::from(i) {
             Some(v) => {
-                Ok(SmartSet {
-                    set: BTreeSet::from([v]),
-                })
+                Ok(SmartSet::<T>::from_range(v, v))
             }
             _ => Err(Error::Cast(i, std::any::type_name::<T>().to_string()))
         }
@@ -133,7 +231,7 @@ where
 //     fn from(i: &[U]) -> Self {
 //         let mut r = SmartSet::<T>::new();
 //         for v in i {
-//             r.set.insert(num::NumCast::from(v).unwrap());
+//             r.insert(num::NumCast::from(v).unwrap());
 //         }
 //         r
 //     }
@@ -146,9 +244,7 @@ where
     type Err = <T as FromStr>::Err;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(SmartSet {
-            set: parse_int_set(s)?,
-        })
+        parse_int_set(s)
     }
 }
 
@@ -157,10 +253,10 @@ where
     T: Num+Ord+Copy + std::str::FromStr + ops::AddAssign,
 {
     type Item = T;
-    type IntoIter = std::collections::btree_set::IntoIter<Self::Item>;
+    type IntoIter = Iter<std::vec::IntoIter<(T,T)>, T>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.set.into_iter()
+        Iter::new(self.ranges.into_iter())
     }
 }
 
@@ -168,11 +264,11 @@ impl<'a, T> IntoIterator for &'a SmartSet<T>
 where
     T: Num+Ord+Copy + std::str::FromStr + ops::AddAssign,
 {
-    type Item = &'a T;
-    type IntoIter = std::collections::btree_set::Iter<'a, T>;
+    type Item = T;
+    type IntoIter = Iter<std::iter::Copied<std::slice::Iter<'a, (T,T)>>, T>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.set.iter()
+        self.iter()
     }
 }
 
@@ -250,10 +346,10 @@ where
 
         loop {
             if let Ok(Some(value)) = seq.next_element::<String>() {
-                r.set.extend(&SmartSet::<T>::from_str(&value).map_err(serde::de::Error::custom)?.set);
+                r.extend(&SmartSet::<T>::from_str(&value).map_err(serde::de::Error::custom)?);
             }
             else if let Some(value) = seq.next_element()? {
-                r.set.insert(value);
+                r.insert(value);
             }
             else {
                 break;
@@ -277,3 +373,62 @@ where
         deserializer.deserialize_any(SmartSetVisitor::<T>::new())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(ranges: &[(u8,u8)]) -> SmartSet<u8> {
+        let mut r = SmartSet::new();
+        for &(low,high) in ranges {
+            r.insert_range(low, high);
+        }
+        r
+    }
+
+    #[test]
+    fn insert_range_disjoint() {
+        let s = set(&[(10,12), (0,2), (5,5)]);
+        assert_eq!(s.ranges(), &[(0,2), (5,5), (10,12)]);
+        assert_eq!(s.len(), 7);
+    }
+
+    #[test]
+    fn insert_range_merges() {
+        // overlapping
+        assert_eq!(set(&[(0,5), (3,8)]).ranges(), &[(0,8)]);
+        // adjacent on both sides
+        assert_eq!(set(&[(0,2), (6,8), (3,5)]).ranges(), &[(0,8)]);
+        // spanning several ranges
+        assert_eq!(set(&[(1,1), (3,3), (5,5), (9,9), (0,6)]).ranges(), &[(0,6), (9,9)]);
+        // reversed bounds
+        assert_eq!(set(&[(8,3)]).ranges(), &[(3,8)]);
+    }
+
+    #[test]
+    fn insert_range_modified() {
+        let mut s = set(&[(0,10)]);
+        assert!(!s.insert_range(2, 8));
+        assert!(!s.insert(10));
+        assert!(s.insert(11));
+        assert!(s.insert_range(20, 30));
+        assert_eq!(s.ranges(), &[(0,11), (20,30)]);
+    }
+
+    #[test]
+    fn insert_range_bounds() {
+        let s = set(&[(250,255), (0,3), (4,249)]);
+        assert_eq!(s.ranges(), &[(0,255)]);
+        assert_eq!(s.len(), 256);
+        assert!(s.contains(&255) && s.contains(&0));
+    }
+
+    #[test]
+    fn parse() {
+        let s: SmartSet<u8> = "0, 2-4,7:8,3".parse().unwrap();
+        assert_eq!(s.ranges(), &[(0,0), (2,4), (7,8)]);
+        assert_eq!(s.iter().collect::<Vec<_>>(), [0, 2, 3, 4, 7, 8]);
+        assert!(!s.contains(&5));
+        assert!("1-x".parse::<SmartSet<u8>>().is_err());
+    }
+}
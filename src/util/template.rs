@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::str::FromStr;
+
+// Format of a placeholder: [[fill]align][0][width][.precision][x|X]
+#[derive(Debug,Clone,Default)]
+struct Spec {
+    fill: Option<char>,
+    align: Option<char>,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+    hex: Option<char>,
+}
+
+#[derive(Debug,Clone)]
+enum Part {
+    Text(String),
+    // env name, its format and the placeholder text
+    Field(String, Spec, String),
+}
+
+// String with {name} or {name:spec} placeholders replaced by env values.
+// Placeholders of names not in env, not following the syntax, or preceded by $ are kept as is
+#[derive(Debug,Clone)]
+pub struct Template(Vec<Part>);
+
+impl Template {
+    pub fn has_fields(&self) -> bool {
+        self.0.iter().any(|x| matches!(x, Part::Field(..)))
+    }
+
+    pub fn render(&self, env: &HashMap<&str, String>) -> String {
+        let mut r = String::new();
+        for p in &self.0 {
+            match p {
+                Part::Text(s) => r.push_str(s),
+                Part::Field(name, spec, text) => match env.get(env_name(name).as_str()) {
+                    Some(v) => r.push_str(&spec.format(v)),
+                    None => r.push_str(text),
+                },
+            }
+        }
+        r
+    }
+}
+
+// var.name is exported as var_name
+fn env_name(name: &str) -> String {
+    match name.strip_prefix("var.") {
+        Some(v) => format!("var_{}", v),
+        None => name.to_string(),
+    }
+}
+
+fn is_name(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+impl FromStr for Template {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut rest = s;
+        while let Some(i) = rest.find(['{', '}']) {
+            text.push_str(&rest[..i]);
+            let c = rest.as_bytes()[i] as char;
+            rest = &rest[i+1..];
+            // doubled braces are literal
+            if rest.starts_with(c) {
+                text.push(c);
+                rest = &rest[1..];
+                continue;
+            }
+            let field = match c {
+                // ${name} is left to the shell
+                '{' if !text.ends_with('$') => rest.find('}').and_then(|j| {
+                    let (name, spec) = match rest[..j].split_once(':') {
+                        Some((n, s)) => (n, s.parse().ok()?),
+                        None => (&rest[..j], Spec::default()),
+                    };
+                    is_name(name).then(|| (Part::Field(name.to_string(), spec, format!("{{{}}}", &rest[..j])), j+1))
+                }),
+                _ => None,
+            };
+            match field {
+                Some((f, len)) => {
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(f);
+                    rest = &rest[len..];
+                }
+                None => text.push(c),
+            }
+        }
+        text.push_str(rest);
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Template(parts))
+    }
+}
+
+impl FromStr for Spec {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut r = Spec::default();
+        let chars: Vec<char> = s.chars().collect();
+        let is_align = |c: &char| matches!(c, '<' | '>' | '^');
+        let mut i = 0;
+        if chars.len() >= 2 && is_align(&chars[1]) {
+            r.fill = Some(chars[0]);
+            r.align = Some(chars[1]);
+            i = 2;
+        }
+        else if chars.first().is_some_and(is_align) {
+            r.align = Some(chars[0]);
+            i = 1;
+        }
+        let digits = |i: &mut usize| -> Option<usize> {
+            let start = *i;
+            while chars.get(*i).is_some_and(|c| c.is_ascii_digit()) {
+                *i += 1;
+            }
+            chars[start..*i].iter().collect::<String>().parse().ok()
+        };
+        if chars.get(i) == Some(&'0') {
+            r.zero = true;
+            i += 1;
+        }
+        r.width = digits(&mut i).unwrap_or(0);
+        if chars.get(i) == Some(&'.') {
+            i += 1;
+            r.precision = Some(digits(&mut i).ok_or(())?);
+        }
+        if let Some(c @ ('x' | 'X')) = chars.get(i) {
+            r.hex = Some(*c);
+            i += 1;
+        }
+        if i != chars.len() {
+            return Err(());
+        }
+        Ok(r)
+    }
+}
+
+impl Spec {
+    fn format(&self, v: &str) -> String {
+        let number = v.parse::<f64>().ok();
+        let s = match (number, self.precision, self.hex) {
+            (Some(n), _, Some('x')) => format!("{:x}", n.round() as i64),
+            (Some(n), _, Some(_)) => format!("{:X}", n.round() as i64),
+            (Some(n), Some(p), None) => format!("{:.*}", p, n),
+            // precision of a string is its maximum length
+            (None, Some(p), _) => v.chars().take(p).collect(),
+            _ => v.to_string(),
+        };
+        let len = s.chars().count();
+        if len >= self.width {
+            return s;
+        }
+        let pad = self.width - len;
+        if self.zero && self.align.is_none() && number.is_some() {
+            let (sign, digits) = match s.strip_prefix('-') {
+                Some(d) => ("-", d),
+                None => ("", s.as_str()),
+            };
+            return format!("{}{}{}", sign, "0".repeat(pad), digits);
+        }
+        let fill = self.fill.unwrap_or(' ').to_string();
+        // numbers are aligned right by default, like rust formatting
+        let align = self.align.unwrap_or(if number.is_some() { '>' } else { '<' });
+        let (left, right) = match align {
+            '>' => (pad, 0),
+            '^' => (pad/2, pad - pad/2),
+            _ => (0, pad),
+        };
+        format!("{}{}{}", fill.repeat(left), s, fill.repeat(right))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str) -> String {
+        let env = HashMap::from([
+            ("value", "0.5".to_string()),
+            ("id", "10".to_string()),
+            ("delta", "-3".to_string()),
+            ("state", "on".to_string()),
+            ("var_bank", "2".to_string()),
+        ]);
+        template.parse::<Template>().unwrap().render(&env)
+    }
+
+    #[test]
+    fn fields() {
+        assert_eq!(render("{id}"), "10");
+        assert_eq!(render("v={value} bank {var.bank}"), "v=0.5 bank 2");
+        assert!(!"no fields".parse::<Template>().unwrap().has_fields());
+        assert!("{id}".parse::<Template>().unwrap().has_fields());
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(render("{{id}}"), "{id}");
+        assert_eq!(render("{{{id}}}"), "{10}");
+        assert_eq!(render("}"), "}");
+        assert_eq!(render("{"), "{");
+        assert_eq!(render("{}"), "{}");
+    }
+
+    #[test]
+    fn shell_braces() {
+        assert_eq!(render("echo ${value}"), "echo ${value}");
+        assert_eq!(render("$id{id}"), "$id10");
+        assert_eq!(render("{print $1}"), "{print $1}");
+        assert_eq!(render("{a: .b}"), "{a: .b}");
+    }
+
+    #[test]
+    fn unknown_names() {
+        assert_eq!(render("{nope}"), "{nope}");
+        assert_eq!(render("{nope:04}"), "{nope:04}");
+        assert_eq!(render("{var.nope}"), "{var.nope}");
+    }
+
+    #[test]
+    fn specs() {
+        assert_eq!(render("{value:.2}"), "0.50");
+        assert_eq!(render("{id:.1}"), "10.0");
+        assert_eq!(render("{state:.1}"), "o");
+        assert_eq!(render("{id:04}"), "0010");
+        assert_eq!(render("{id:x}|{id:04X}"), "a|000A");
+        assert_eq!(render("{id:5}|{state:4}|"), "   10|on  |");
+        assert_eq!(render("{id:<4}|{id:*^6}|{state:->4}"), "10  |**10**|--on");
+    }
+
+    #[test]
+    fn negative_zero_padding() {
+        assert_eq!(render("{delta:04}"), "-003");
+        assert_eq!(render("{delta:>04}"), "  -3");
+        assert_eq!(render("{delta:02}"), "-3");
+    }
+
+    #[test]
+    fn invalid_specs() {
+        assert_eq!(render("{id:z}"), "{id:z}");
+        assert_eq!(render("{id:.}"), "{id:.}");
+        assert_eq!(render("{id:4.2.1}"), "{id:4.2.1}");
+        assert_eq!(render("{id:xx}"), "{id:xx}");
+        assert_eq!(render("{id:}"), "10");
+    }
+}